@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use aws_types::{
+    credentials::{self, CredentialsError},
+    Credentials,
+};
+
+use super::{cache, mfa, vault};
+use crate::aws::{profile::AWSProfile, region};
+
+// AssumeRoleで使用するセッション名の接頭辞
+const SESSION_NAME_PREFIX: &str = "s3-explorer";
+
+/// `source_profile`をたどってロールを引き受け、一時認証情報を取得する
+pub async fn assume(
+    profiles: &HashMap<String, AWSProfile>,
+    profile: &AWSProfile,
+) -> credentials::Result {
+    // 引き受けるロールのリスト(末端のプロファイルから順に格納)と
+    // 起点となる認証情報を持つプロファイルを取得する
+    let mut roles = Vec::<&AWSProfile>::new();
+    let mut current = profile;
+    let base = loop {
+        if current.role_arn.is_none() {
+            break current;
+        }
+        roles.push(current);
+
+        let source_name = current.source_profile.as_ref().ok_or_else(|| {
+            CredentialsError::invalid_configuration(format!(
+                "profile `{}` has role_arn but no source_profile",
+                current.name
+            ))
+        })?;
+
+        // 自身を参照している場合は自身の固定の認証情報を起点とする
+        if source_name == &current.name {
+            break current;
+        }
+
+        let next = profiles.get(source_name).ok_or_else(|| {
+            CredentialsError::invalid_configuration(format!(
+                "source_profile `{}` of profile `{}` does not exist",
+                source_name, current.name
+            ))
+        })?;
+
+        // 循環参照のチェック
+        if roles.iter().any(|p| p.name == next.name) {
+            return Err(CredentialsError::invalid_configuration(format!(
+                "profile `{}` has a circular source_profile chain",
+                profile.name
+            )));
+        }
+        current = next;
+    };
+
+    // 保管庫の認証情報を起点とする場合は、取得した一時認証情報もディスクに保存しない
+    let persist = !vault::contains(&base.name);
+
+    // 起点のプロファイルから順にロールを引き受ける
+    // キャッシュは引き受ける際の認証情報ごとに保持するため、起点の認証情報は毎回取得する
+    let mut cred = super::source_credentials(base).await?;
+    for role in roles.iter().rev() {
+        cred = match cache::get(&cache_key(role, &cred)) {
+            Some(c) => c,
+            None => assume_one(role, cred, persist).await?,
+        };
+    }
+
+    Ok(cred)
+}
+
+// 指定の認証情報を使用して1つのロールを引き受ける
+async fn assume_one(role: &AWSProfile, source: Credentials, persist: bool) -> credentials::Result {
+    let role_arn = role.role_arn.clone().unwrap_or_default();
+    let key = cache_key(role, &source);
+
+    // MFAが必要な場合はコードの入力を要求する
+    // 入力待ちの間に他のリクエストで取得済みとなっている可能性があるため、
+    // ロック取得後にキャッシュを確認する
+    let mut token = None;
+    let _guard = match &role.mfa_serial {
+        Some(serial) => {
+            let guard = mfa::PROMPT_LOCK.lock().await;
+            if let Some(cred) = cache::get(&key) {
+                return Ok(cred);
+            }
+            let code = mfa::request_code(&role.name, serial).await?;
+            token = Some((serial.clone(), code));
+            Some(guard)
+        }
+        None => None,
+    };
+
+    // STS client
+    let region_name = region::resolve(None, role.region.as_deref()).name;
+    let config = aws_config::from_env()
+        .region(aws_sdk_sts::Region::new(region_name))
+        .credentials_provider(source)
+        .load()
+        .await;
+    let client = aws_sdk_sts::Client::new(&config);
+
+    let mut req = client
+        .assume_role()
+        .role_arn(role_arn)
+        .role_session_name(session_name(role));
+    if let Some(id) = &role.external_id {
+        req = req.external_id(id);
+    }
+    if let Some(d) = role.duration_seconds {
+        req = req.duration_seconds(d);
+    }
+    if let Some((serial, code)) = token {
+        req = req.serial_number(serial).token_code(code);
+    }
+
+    let res = req.send().await.map_err(CredentialsError::provider_error)?;
+    let cred = super::from_sts(res.credentials(), "AssumeRole")?;
+
+    // MFAを使用した場合は再入力を避けるためディスクにも保存する
+    if role.mfa_serial.is_some() && persist {
+        cache::persist(&key, &cred);
+    } else {
+        cache::insert(&key, &cred);
+    }
+    Ok(cred)
+}
+
+// セッション名を取得する
+// 指定がない場合はタイムスタンプから生成
+fn session_name(role: &AWSProfile) -> String {
+    match &role.role_session_name {
+        Some(name) => name.clone(),
+        None => format!("{}-{}", SESSION_NAME_PREFIX, chrono::Utc::now().timestamp()),
+    }
+}
+
+// キャッシュのキー
+// 設定や引き受ける際の認証情報が変更された場合にキャッシュを使用しないよう、
+// MFAデバイスと引き受ける際のアクセスキーを含める
+fn cache_key(role: &AWSProfile, source: &Credentials) -> String {
+    format!(
+        "assume_role:{}:{}:{}:{}",
+        role.name,
+        role.role_arn.clone().unwrap_or_default(),
+        role.mfa_serial.clone().unwrap_or_default(),
+        source.access_key_id()
+    )
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use aws_types::Credentials;
+use once_cell::sync::Lazy;
+
+use crate::config;
+
+// 有効期限のこの時間前になったら再取得する
+const REFRESH_BEFORE_EXPIRY: Duration = Duration::from_secs(5 * 60);
+
+// 一時認証情報のキャッシュ
+static CACHE: Lazy<Mutex<HashMap<String, Credentials>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// ディスクに保存する認証情報
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    // UNIX時間(秒)
+    expiration: Option<u64>,
+}
+
+/// 有効期限内のキャッシュ済み認証情報を取得
+/// メモリ上に存在しない場合はディスクに保存されたものを参照する
+pub fn get(key: &str) -> Option<Credentials> {
+    let mut cache = CACHE.lock().unwrap();
+    if let Some(cred) = cache.get(key) {
+        if is_fresh(cred) {
+            return Some(cred.clone());
+        }
+    }
+
+    let cred = read_from_disk(key)?;
+    if !is_fresh(&cred) {
+        let _ = std::fs::remove_file(disk_path(key));
+        return None;
+    }
+    cache.insert(key.to_string(), cred.clone());
+    Some(cred)
+}
+
+/// 認証情報をキャッシュに追加
+pub fn insert(key: &str, cred: &Credentials) {
+    CACHE.lock().unwrap().insert(key.to_string(), cred.clone());
+}
+
+/// 認証情報をキャッシュに追加し、有効期限までディスクにも保存する
+pub fn persist(key: &str, cred: &Credentials) {
+    insert(key, cred);
+
+    let data = CachedCredentials {
+        access_key_id: cred.access_key_id().to_string(),
+        secret_access_key: cred.secret_access_key().to_string(),
+        session_token: cred.session_token().map(|t| t.to_string()),
+        expiration: cred
+            .expiry()
+            .and_then(|e| e.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs()),
+    };
+    let text = serde_json::to_string(&data).unwrap();
+
+    // 本人のみ読み書きできる権限でファイルを作成
+    if let Err(err) = config::write_private(&disk_path(key), text.as_bytes()) {
+        println!("credential cache write error: {}", err);
+    }
+}
+
+// ディスクから認証情報を読み込む
+fn read_from_disk(key: &str) -> Option<Credentials> {
+    let text = std::fs::read_to_string(disk_path(key)).ok()?;
+    let data: CachedCredentials = serde_json::from_str(&text).ok()?;
+    Some(Credentials::new(
+        data.access_key_id,
+        data.secret_access_key,
+        data.session_token,
+        data.expiration.map(|e| UNIX_EPOCH + Duration::from_secs(e)),
+        "Cached",
+    ))
+}
+
+// キャッシュファイルのパス
+// キー内のファイル名に使用できない文字は置換する
+fn disk_path(key: &str) -> PathBuf {
+    let name = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    config::cache_dir().join(format!("{}.json", name))
+}
+
+// 有効期限まで十分な時間が残っているか
+fn is_fresh(cred: &Credentials) -> bool {
+    match cred.expiry() {
+        Some(expiry) => match expiry.checked_sub(REFRESH_BEFORE_EXPIRY) {
+            Some(t) => SystemTime::now() < t,
+            None => false,
+        },
+        None => true,
+    }
+}
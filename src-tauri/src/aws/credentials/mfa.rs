@@ -0,0 +1,174 @@
+use std::{collections::HashMap, future::Future, sync::Mutex, time::Duration};
+
+use aws_types::credentials::{self, CredentialsError};
+use once_cell::sync::{Lazy, OnceCell};
+use tauri::{AppHandle, Manager};
+use tokio::sync::oneshot;
+
+use super::{cache, vault};
+use crate::aws::{profile::AWSProfile, region};
+
+/// MFAコードの入力を要求するイベント名
+pub const MFA_REQUIRED_EVENT: &str = "mfa-required";
+
+// MFAコードの入力を待機する時間
+const INPUT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+// イベント通知用のハンドル
+static APP_HANDLE: OnceCell<AppHandle> = OnceCell::new();
+
+// 入力待ちのリクエスト(プロファイル名 → 送信側)
+static PENDING: Lazy<Mutex<HashMap<String, oneshot::Sender<String>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 同時に複数の入力要求が発生しないようにするためのロック
+pub static PROMPT_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+tokio::task_local! {
+    // 入力要求を行わずにエラーとする場合はtrue
+    static NO_PROMPT: bool;
+}
+
+/// MFAコードの入力が必要だが要求できない
+#[derive(Debug)]
+pub struct MfaCodeRequired {
+    pub profile: String,
+}
+
+impl std::fmt::Display for MfaCodeRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MFA code is required for profile `{}`", self.profile)
+    }
+}
+
+impl std::error::Error for MfaCodeRequired {}
+
+/// MFAコード入力要求のイベント内容
+#[derive(Clone, serde::Serialize)]
+pub struct MfaRequired {
+    pub profile: String,
+    pub mfa_serial: String,
+}
+
+/// イベント通知用のハンドルを設定する
+pub fn init(app: AppHandle) {
+    let _ = APP_HANDLE.set(app);
+}
+
+/// イベント通知用のハンドルを取得する
+pub fn app_handle() -> Option<&'static AppHandle> {
+    APP_HANDLE.get()
+}
+
+/// 入力されたMFAコードを待機中のリクエストへ渡す
+pub fn submit_code(profile: &str, code: String) -> Result<(), String> {
+    let code = code.trim().to_string();
+    if code.len() != 6 || !code.chars().all(|c| c.is_ascii_digit()) {
+        return Err("MFA code must be 6 digits.".to_string());
+    }
+
+    match PENDING.lock().unwrap().remove(profile) {
+        Some(tx) => tx
+            .send(code)
+            .map_err(|_| "MFA request has already been closed.".to_string()),
+        None => Err(format!("no pending MFA request for `{}`", profile)),
+    }
+}
+
+/// MFAコードの入力をキャンセルする
+pub fn cancel(profile: &str) {
+    // 送信側を破棄することで待機中のリクエストをエラーにする
+    PENDING.lock().unwrap().remove(profile);
+}
+
+/// MFAコードの入力要求を行わずに処理を実行する
+/// 入力が必要な場合は`MfaCodeRequired`のエラーとなる
+pub async fn without_prompt<F: Future>(f: F) -> F::Output {
+    NO_PROMPT.scope(true, f).await
+}
+
+/// UIにMFAコードの入力を要求し、入力されるまで待機する
+pub async fn request_code(profile: &str, mfa_serial: &str) -> Result<String, CredentialsError> {
+    if NO_PROMPT.try_with(|v| *v).unwrap_or(false) {
+        return Err(CredentialsError::not_loaded(MfaCodeRequired {
+            profile: profile.to_string(),
+        }));
+    }
+
+    let app = APP_HANDLE.get().ok_or_else(|| {
+        CredentialsError::not_loaded("MFA code is required but cannot be requested")
+    })?;
+
+    let (tx, rx) = oneshot::channel::<String>();
+    PENDING.lock().unwrap().insert(profile.to_string(), tx);
+
+    let payload = MfaRequired {
+        profile: profile.to_string(),
+        mfa_serial: mfa_serial.to_string(),
+    };
+    if let Err(err) = app.emit_all(MFA_REQUIRED_EVENT, payload) {
+        PENDING.lock().unwrap().remove(profile);
+        return Err(CredentialsError::unhandled(err.to_string()));
+    }
+
+    match tokio::time::timeout(INPUT_TIMEOUT, rx).await {
+        Ok(Ok(code)) => Ok(code),
+        Ok(Err(_)) => Err(CredentialsError::not_loaded(format!(
+            "MFA code input for `{}` was cancelled",
+            profile
+        ))),
+        Err(_) => {
+            PENDING.lock().unwrap().remove(profile);
+            Err(CredentialsError::provider_timed_out(INPUT_TIMEOUT))
+        }
+    }
+}
+
+/// MFAコードを使用してGetSessionTokenで一時認証情報を取得する
+pub async fn session_token(p: &AWSProfile, mfa_serial: &str) -> credentials::Result {
+    // アクセスキーやMFAデバイスが変更された場合にキャッシュを使用しないよう、キーに含める
+    let source = super::static_credentials(p)?;
+    let key = format!(
+        "mfa_session:{}:{}:{}",
+        p.name,
+        mfa_serial,
+        source.access_key_id()
+    );
+
+    // 入力待ちの間に他のリクエストで取得済みとなっている可能性があるため、
+    // ロック取得後にキャッシュを確認する
+    let _guard = PROMPT_LOCK.lock().await;
+    if let Some(cred) = cache::get(&key) {
+        return Ok(cred);
+    }
+
+    let code = request_code(&p.name, mfa_serial).await?;
+
+    // STS client
+    let region_name = region::resolve(None, p.region.as_deref()).name;
+    let config = aws_config::from_env()
+        .region(aws_sdk_sts::Region::new(region_name))
+        .credentials_provider(source)
+        .load()
+        .await;
+    let client = aws_sdk_sts::Client::new(&config);
+
+    let mut req = client
+        .get_session_token()
+        .serial_number(mfa_serial)
+        .token_code(code);
+    if let Some(d) = p.duration_seconds {
+        req = req.duration_seconds(d);
+    }
+    let res = req.send().await.map_err(CredentialsError::provider_error)?;
+
+    let cred = super::from_sts(res.credentials(), "GetSessionToken")?;
+
+    // 保管庫の認証情報から取得した場合は、ディスクに平文で残さないようメモリ上のみに保持する
+    if vault::contains(&p.name) {
+        cache::insert(&key, &cred);
+    } else {
+        cache::persist(&key, &cred);
+    }
+    Ok(cred)
+}
@@ -0,0 +1,143 @@
+use std::time::{Duration, SystemTime};
+
+use aws_config::meta::credentials::LazyCachingCredentialsProvider;
+use aws_types::{
+    credentials::{self, future, CredentialsError, ProvideCredentials},
+    Credentials,
+};
+
+use super::profile::{self, AWSProfile};
+
+pub mod assume_role;
+mod cache;
+pub mod mfa;
+pub mod process;
+pub mod sources;
+pub mod sso;
+pub mod vault;
+
+// 認証情報の取得のタイムアウト
+// MFAコードの入力待ちを含むため、入力の待機時間より長くする
+const LOAD_TIMEOUT: Duration = Duration::from_secs(6 * 60);
+
+/// プロファイルの設定から認証情報を解決し、有効期限までキャッシュするプロバイダを生成する
+/// 署名のたびにファイルの読み込みやAssumeRoleを行わないようにする
+/// (ファイルが更新された場合はセッションごと作り直すため、キャッシュも破棄される)
+pub fn profile_provider(profile_name: impl Into<String>) -> LazyCachingCredentialsProvider {
+    LazyCachingCredentialsProvider::builder()
+        .load(ProfileProvider::new(profile_name))
+        .load_timeout(LOAD_TIMEOUT)
+        .build()
+}
+
+/// プロファイルの設定から認証情報を解決するプロバイダ
+#[derive(Debug)]
+pub struct ProfileProvider {
+    profile_name: String,
+}
+
+impl ProfileProvider {
+    pub fn new(profile_name: impl Into<String>) -> Self {
+        ProfileProvider {
+            profile_name: profile_name.into(),
+        }
+    }
+}
+
+impl ProvideCredentials for ProfileProvider {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(resolve(&self.profile_name))
+    }
+}
+
+/// プロファイル名から認証情報を解決する
+pub async fn resolve(profile_name: &str) -> credentials::Result {
+    // 疑似プロファイルの場合は環境から取得
+    if let Some(source) = sources::Source::from_profile_name(profile_name) {
+        return sources::credentials(source).await;
+    }
+
+    let profiles = profile::read_profiles().map_err(CredentialsError::invalid_configuration)?;
+    let p = profiles.get(profile_name).ok_or_else(|| {
+        CredentialsError::not_loaded(format!("profile `{}` does not exist", profile_name))
+    })?;
+
+    // ロールの指定がある場合はAssumeRoleで一時認証情報を取得
+    if p.role_arn.is_some() {
+        return assume_role::assume(&profiles, p).await;
+    }
+
+    // MFAの指定がある場合はGetSessionTokenで一時認証情報を取得
+    if let Some(serial) = &p.mfa_serial {
+        return mfa::session_token(p, serial).await;
+    }
+
+    source_credentials(p).await
+}
+
+/// ロールを引き受ける起点となる認証情報を取得
+pub(crate) async fn source_credentials(p: &AWSProfile) -> credentials::Result {
+    // SSOの設定がある場合はキャッシュ済みのトークンから取得
+    if let Some(sso) = &p.sso {
+        return sso::role_credentials(p, sso).await;
+    }
+
+    // 外部コマンドの指定がある場合は実行結果から取得
+    if let Some(command) = &p.credential_process {
+        return process::credentials(p, command).await;
+    }
+
+    static_credentials(p)
+}
+
+/// プロファイルに設定された固定の認証情報を取得
+/// 保管庫に保存されている場合はそちらを優先する
+pub(crate) fn static_credentials(p: &AWSProfile) -> credentials::Result {
+    if let Some(result) = vault::credentials(&p.name) {
+        return result;
+    }
+
+    match (&p.access_key_id, &p.secret_access_key) {
+        (Some(id), Some(secret)) => Ok(Credentials::new(
+            id,
+            secret,
+            p.session_token.clone(),
+            None,
+            "Statics",
+        )),
+        _ => Err(CredentialsError::not_loaded(format!(
+            "profile `{}` does not have credentials",
+            p.name
+        ))),
+    }
+}
+
+/// STSのレスポンスに含まれる一時認証情報を変換する
+pub(crate) fn from_sts(
+    cred: Option<&aws_sdk_sts::model::Credentials>,
+    provider_name: &'static str,
+) -> credentials::Result {
+    let cred = cred
+        .ok_or_else(|| CredentialsError::unhandled("STS response does not contain credentials"))?;
+
+    match (cred.access_key_id(), cred.secret_access_key()) {
+        (Some(id), Some(secret)) => {
+            let expiry = cred
+                .expiration()
+                .and_then(|d| SystemTime::try_from(*d).ok());
+            Ok(Credentials::new(
+                id,
+                secret,
+                cred.session_token().map(|t| t.to_string()),
+                expiry,
+                provider_name,
+            ))
+        }
+        _ => Err(CredentialsError::unhandled(
+            "STS response does not contain access key",
+        )),
+    }
+}
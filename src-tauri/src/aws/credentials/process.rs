@@ -0,0 +1,96 @@
+use std::time::{Duration, SystemTime};
+
+use aws_types::{
+    credentials::{self, CredentialsError},
+    Credentials,
+};
+
+use super::cache;
+use crate::aws::profile::AWSProfile;
+
+// 対応している出力形式のバージョン
+const SUPPORTED_VERSION: i64 = 1;
+
+// コマンドの実行を待機する時間
+// ブラウザでのログインを待つコマンドもあるため長めに設定する
+const PROCESS_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
+// `credential_process`の出力
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessOutput {
+    version: i64,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    expiration: Option<String>,
+}
+
+/// `credential_process`に指定されたコマンドを実行して認証情報を取得する
+pub async fn credentials(p: &AWSProfile, command: &str) -> credentials::Result {
+    let key = format!("process:{}:{}", p.name, command);
+    if let Some(cred) = cache::get(&key) {
+        return Ok(cred);
+    }
+
+    // シェル経由でコマンドを実行
+    // 応答がない場合に認証情報の取得が止まらないよう、タイムアウトした場合は終了させる
+    let mut cmd = shell_command(command);
+    cmd.kill_on_drop(true);
+    let output = tokio::time::timeout(PROCESS_TIMEOUT, cmd.output())
+        .await
+        .map_err(|_| CredentialsError::provider_timed_out(PROCESS_TIMEOUT))?
+        .map_err(CredentialsError::provider_error)?;
+    if !output.status.success() {
+        return Err(CredentialsError::provider_error(format!(
+            "credential_process of profile `{}` failed ({}): {}",
+            p.name,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let data: ProcessOutput =
+        serde_json::from_slice(&output.stdout).map_err(CredentialsError::invalid_configuration)?;
+    if data.version != SUPPORTED_VERSION {
+        return Err(CredentialsError::invalid_configuration(format!(
+            "unsupported credential_process version: {}",
+            data.version
+        )));
+    }
+
+    // 有効期限の指定がない場合は無期限として扱う
+    let expiry = match &data.expiration {
+        Some(e) => {
+            let d = chrono::DateTime::parse_from_rfc3339(e)
+                .map_err(CredentialsError::invalid_configuration)?;
+            Some(SystemTime::from(d))
+        }
+        None => None,
+    };
+
+    let cred = Credentials::new(
+        data.access_key_id,
+        data.secret_access_key,
+        data.session_token,
+        expiry,
+        "CredentialProcess",
+    );
+    cache::insert(&key, &cred);
+    Ok(cred)
+}
+
+// OSのシェルでコマンドを実行する
+#[cfg(windows)]
+fn shell_command(command: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
@@ -0,0 +1,265 @@
+use aws_types::credentials::{self, CredentialsError, ProvideCredentials};
+
+use super::cache;
+use crate::aws::region;
+
+/// 疑似プロファイル名のプレフィックス
+/// AWSのプロファイル名や接続設定の名称には使用できない
+pub const PSEUDO_PROFILE_PREFIX: &str = "@";
+
+// 環境変数の認証情報
+const ACCESS_KEY_ID_ENV: &str = "AWS_ACCESS_KEY_ID";
+
+// Web ID トークン
+const WEB_IDENTITY_TOKEN_FILE_ENV: &str = "AWS_WEB_IDENTITY_TOKEN_FILE";
+const ROLE_ARN_ENV: &str = "AWS_ROLE_ARN";
+const ROLE_SESSION_NAME_ENV: &str = "AWS_ROLE_SESSION_NAME";
+
+/// STSのエンドポイントを差し替える環境変数
+/// ローカルのスタブサーバーで動作確認する場合などに使用する
+pub const STS_ENDPOINT_ENV: &str = "AWS_ENDPOINT_URL_STS";
+
+// ECSのコンテナ認証情報
+const CONTAINER_RELATIVE_URI_ENV: &str = "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI";
+const CONTAINER_FULL_URI_ENV: &str = "AWS_CONTAINER_CREDENTIALS_FULL_URI";
+
+// インスタンスメタデータ
+// エンドポイントを指定した場合はEC2以外でもローカルのスタブサーバーで確認できる
+const IMDS_ENDPOINT_ENV: &str = "AWS_EC2_METADATA_SERVICE_ENDPOINT";
+const IMDS_DISABLED_ENV: &str = "AWS_EC2_METADATA_DISABLED";
+
+/// プロファイル以外の認証情報の取得元
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    // `AWS_ACCESS_KEY_ID`などの環境変数
+    Environment,
+    // `AWS_WEB_IDENTITY_TOKEN_FILE`のトークンでAssumeRoleWithWebIdentity
+    WebIdentity,
+    // ECSのコンテナ認証情報エンドポイント
+    Container,
+    // EC2のインスタンスメタデータ(IMDSv2)
+    InstanceMetadata,
+}
+
+/// 取得元の情報
+#[derive(serde::Serialize)]
+pub struct SourceInfo {
+    pub profile: String,
+    pub source: Source,
+    pub available: bool,
+    // 参照する環境変数の値など
+    pub detail: Option<String>,
+}
+
+impl Source {
+    pub const ALL: [Source; 4] = [
+        Source::Environment,
+        Source::WebIdentity,
+        Source::Container,
+        Source::InstanceMetadata,
+    ];
+
+    /// 疑似プロファイル名
+    pub fn profile_name(&self) -> String {
+        let name = match self {
+            Source::Environment => "env",
+            Source::WebIdentity => "web-identity",
+            Source::Container => "container",
+            Source::InstanceMetadata => "instance-metadata",
+        };
+        format!("{}{}", PSEUDO_PROFILE_PREFIX, name)
+    }
+
+    /// 疑似プロファイル名から取得元を判定する
+    pub fn from_profile_name(name: &str) -> Option<Source> {
+        Source::ALL
+            .iter()
+            .find(|s| s.profile_name() == name)
+            .copied()
+    }
+
+    /// 現在の環境で使用できるか
+    pub fn is_available(&self) -> bool {
+        match self {
+            Source::Environment => env(ACCESS_KEY_ID_ENV).is_some(),
+            Source::WebIdentity => {
+                env(WEB_IDENTITY_TOKEN_FILE_ENV).is_some() && env(ROLE_ARN_ENV).is_some()
+            }
+            Source::Container => {
+                env(CONTAINER_RELATIVE_URI_ENV).is_some() || env(CONTAINER_FULL_URI_ENV).is_some()
+            }
+            Source::InstanceMetadata => {
+                if env(IMDS_DISABLED_ENV).map_or(false, |v| v.eq_ignore_ascii_case("true")) {
+                    return false;
+                }
+                env(IMDS_ENDPOINT_ENV).is_some() || is_ec2()
+            }
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        match self {
+            Source::Environment => env(ACCESS_KEY_ID_ENV),
+            Source::WebIdentity => env(ROLE_ARN_ENV),
+            Source::Container => {
+                env(CONTAINER_FULL_URI_ENV).or_else(|| env(CONTAINER_RELATIVE_URI_ENV))
+            }
+            Source::InstanceMetadata => env(IMDS_ENDPOINT_ENV),
+        }
+    }
+}
+
+/// すべての取得元の情報
+pub fn list() -> Vec<SourceInfo> {
+    Source::ALL
+        .iter()
+        .map(|s| SourceInfo {
+            profile: s.profile_name(),
+            source: *s,
+            available: s.is_available(),
+            detail: s.detail(),
+        })
+        .collect()
+}
+
+/// 使用できる取得元の疑似プロファイル名
+pub fn available_profile_names() -> Vec<String> {
+    Source::ALL
+        .iter()
+        .filter(|s| s.is_available())
+        .map(|s| s.profile_name())
+        .collect()
+}
+
+/// 指定の取得元から認証情報を取得する
+pub async fn credentials(source: Source) -> credentials::Result {
+    let key = format!("source:{}", source.profile_name());
+    if let Some(cred) = cache::get(&key) {
+        return Ok(cred);
+    }
+
+    let cred = match source {
+        // 環境変数は変更される可能性があるためキャッシュしない
+        Source::Environment => {
+            return aws_config::environment::credentials::EnvironmentVariableCredentialsProvider::new()
+                .provide_credentials()
+                .await
+        }
+        Source::WebIdentity => web_identity().await?,
+        Source::Container => {
+            aws_config::ecs::EcsCredentialsProvider::builder()
+                .build()
+                .provide_credentials()
+                .await?
+        }
+        Source::InstanceMetadata => {
+            aws_config::imds::credentials::ImdsCredentialsProvider::builder()
+                .build()
+                .provide_credentials()
+                .await?
+        }
+    };
+
+    cache::insert(&key, &cred);
+    Ok(cred)
+}
+
+// トークンファイルを読み込みAssumeRoleWithWebIdentityで一時認証情報を取得する
+async fn web_identity() -> credentials::Result {
+    let token_file = env(WEB_IDENTITY_TOKEN_FILE_ENV).ok_or_else(|| {
+        CredentialsError::not_loaded(format!("{} is not set", WEB_IDENTITY_TOKEN_FILE_ENV))
+    })?;
+    let role_arn = env(ROLE_ARN_ENV)
+        .ok_or_else(|| CredentialsError::not_loaded(format!("{} is not set", ROLE_ARN_ENV)))?;
+    let session_name = env(ROLE_SESSION_NAME_ENV)
+        .unwrap_or_else(|| format!("s3-explorer-{}", chrono::Utc::now().timestamp()));
+
+    // トークンは更新される可能性があるため毎回読み込む
+    let token =
+        std::fs::read_to_string(&token_file).map_err(CredentialsError::invalid_configuration)?;
+
+    // STS client
+    // リトライ、タイムアウトの設定は共通のAWS Configから引き継ぐ
+    // AssumeRoleWithWebIdentityは署名が不要なため認証情報は設定しない
+    let shared = crate::aws::default_config(&region::resolve(None, None).name).await;
+    let mut builder = aws_sdk_sts::config::Builder::from(&shared);
+    builder.set_credentials_provider(None);
+    if let Some(uri) = sts_endpoint(env(STS_ENDPOINT_ENV))? {
+        builder = builder.endpoint_resolver(aws_sdk_sts::Endpoint::immutable(uri));
+    }
+    let client = aws_sdk_sts::Client::from_conf(builder.build());
+
+    let res = client
+        .assume_role_with_web_identity()
+        .role_arn(role_arn)
+        .role_session_name(session_name)
+        .web_identity_token(token.trim())
+        .send()
+        .await
+        .map_err(CredentialsError::provider_error)?;
+
+    super::from_sts(res.credentials(), "WebIdentityToken")
+}
+
+// `AWS_ENDPOINT_URL_STS`で指定されたSTSのエンドポイント
+// 未指定の場合は`None`、URLとして不正な場合はエラー
+fn sts_endpoint(url: Option<String>) -> Result<Option<http::Uri>, CredentialsError> {
+    url.map(|u| {
+        u.trim()
+            .parse::<http::Uri>()
+            .map_err(CredentialsError::invalid_configuration)
+    })
+    .transpose()
+}
+
+// 空でない環境変数の値
+fn env(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
+
+// EC2上で実行されているか(Nitroベースのインスタンスのみ判定)
+fn is_ec2() -> bool {
+    [
+        "/sys/devices/virtual/dmi/id/board_asset_tag",
+        "/sys/hypervisor/uuid",
+    ]
+    .iter()
+    .filter_map(|p| std::fs::read_to_string(p).ok())
+    .any(|v| v.starts_with("i-") || v.starts_with("ec2"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pseudo_profile_names_round_trip() {
+        for source in Source::ALL {
+            let name = source.profile_name();
+            assert!(name.starts_with(PSEUDO_PROFILE_PREFIX));
+            assert_eq!(Source::from_profile_name(&name), Some(source));
+        }
+        assert_eq!(
+            Source::from_profile_name("@web-identity"),
+            Some(Source::WebIdentity)
+        );
+    }
+
+    #[test]
+    fn unknown_names_are_not_pseudo_profiles() {
+        assert_eq!(Source::from_profile_name("env"), None);
+        assert_eq!(Source::from_profile_name("@unknown"), None);
+        assert_eq!(Source::from_profile_name("default"), None);
+    }
+
+    #[test]
+    fn sts_endpoint_override() {
+        assert_eq!(sts_endpoint(None).unwrap(), None);
+        assert_eq!(
+            sts_endpoint(Some(" http://127.0.0.1:4566 ".to_string())).unwrap(),
+            Some("http://127.0.0.1:4566".parse::<http::Uri>().unwrap())
+        );
+        assert!(sts_endpoint(Some("http://[invalid".to_string())).is_err());
+    }
+}
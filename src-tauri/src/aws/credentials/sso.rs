@@ -0,0 +1,241 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use aws_types::{
+    credentials::{self, CredentialsError},
+    Credentials,
+};
+use sha1::{Digest, Sha1};
+
+use super::cache;
+use crate::aws::profile::{AWSProfile, SsoConfig};
+
+/// SSOポータルのエンドポイントを差し替える環境変数
+/// ローカルのスタブサーバーで動作確認する場合などに使用する
+pub const SSO_ENDPOINT_ENV: &str = "AWS_ENDPOINT_URL_SSO";
+
+/// SSOのアクセストークンの有効期限切れ
+#[derive(Debug)]
+pub struct SsoTokenExpired {
+    pub profile: String,
+}
+
+impl std::fmt::Display for SsoTokenExpired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The SSO session for profile `{}` has expired. Run `aws sso login --profile {}` to login again.",
+            self.profile, self.profile
+        )
+    }
+}
+
+impl std::error::Error for SsoTokenExpired {}
+
+// `~/.aws/sso/cache`に保存されたトークン
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedToken {
+    access_token: String,
+    expires_at: String,
+}
+
+/// キャッシュ済みのSSOトークンからロールの一時認証情報を取得する
+pub async fn role_credentials(p: &AWSProfile, sso: &SsoConfig) -> credentials::Result {
+    let key = format!("sso:{}:{}:{}", p.name, sso.account_id, sso.role_name);
+    if let Some(cred) = cache::get(&key) {
+        return Ok(cred);
+    }
+
+    let token = read_token(p, sso)?;
+
+    // SSO client
+    let config = aws_config::from_env()
+        .region(aws_sdk_sso::Region::new(sso.region.clone()))
+        .load()
+        .await;
+    let mut builder = aws_sdk_sso::config::Builder::from(&config);
+    if let Ok(url) = std::env::var(SSO_ENDPOINT_ENV) {
+        let uri = url
+            .parse::<http::Uri>()
+            .map_err(CredentialsError::invalid_configuration)?;
+        builder = builder.endpoint_resolver(aws_sdk_sso::Endpoint::immutable(uri));
+    }
+    let client = aws_sdk_sso::Client::from_conf(builder.build());
+
+    let res = client
+        .get_role_credentials()
+        .role_name(&sso.role_name)
+        .account_id(&sso.account_id)
+        .access_token(token)
+        .send()
+        .await;
+    let res = match res {
+        Ok(v) => v,
+        Err(aws_sdk_sso::types::SdkError::ServiceError { err, .. })
+            if err.is_unauthorized_exception() =>
+        {
+            // トークンが失効している場合
+            return Err(CredentialsError::not_loaded(SsoTokenExpired {
+                profile: p.name.clone(),
+            }));
+        }
+        Err(err) => return Err(CredentialsError::provider_error(err)),
+    };
+
+    let role = res
+        .role_credentials()
+        .ok_or_else(|| CredentialsError::unhandled("SSO response does not contain credentials"))?;
+    let cred = match (role.access_key_id(), role.secret_access_key()) {
+        (Some(id), Some(secret)) => Credentials::new(
+            id,
+            secret,
+            role.session_token().map(|t| t.to_string()),
+            // 有効期限はミリ秒で返却される
+            Some(UNIX_EPOCH + Duration::from_millis(role.expiration() as u64)),
+            "SSO",
+        ),
+        _ => {
+            return Err(CredentialsError::unhandled(
+                "SSO response does not contain access key",
+            ))
+        }
+    };
+
+    cache::insert(&key, &cred);
+    Ok(cred)
+}
+
+// キャッシュ済みのSSOトークンを読み込み、有効期限を確認する
+fn read_token(p: &AWSProfile, sso: &SsoConfig) -> Result<String, CredentialsError> {
+    read_token_file(&p.name, &token_path(sso), SystemTime::now())
+}
+
+// トークンファイルを読み込み、`now`の時点で有効なアクセストークンを返却する
+fn read_token_file(
+    profile: &str,
+    path: &Path,
+    now: SystemTime,
+) -> Result<String, CredentialsError> {
+    let expired = || {
+        CredentialsError::not_loaded(SsoTokenExpired {
+            profile: profile.to_string(),
+        })
+    };
+
+    // トークンファイルが存在しない場合はログインしていない
+    let text = std::fs::read_to_string(path).map_err(|_| expired())?;
+    let token: CachedToken =
+        serde_json::from_str(&text).map_err(CredentialsError::invalid_configuration)?;
+
+    // 旧形式では末尾が`UTC`となっているため補正して解析する
+    let expires_at = token.expires_at.replace("UTC", "Z");
+    let expires_at = chrono::DateTime::parse_from_rfc3339(&expires_at)
+        .map_err(CredentialsError::invalid_configuration)?;
+    let expires_at: SystemTime = expires_at.into();
+    if expires_at <= now {
+        return Err(expired());
+    }
+
+    Ok(token.access_token)
+}
+
+// トークンファイルのパス
+fn token_path(sso: &SsoConfig) -> PathBuf {
+    let mut path = dirs::home_dir().unwrap_or_default();
+    path.push(".aws");
+    path.push("sso");
+    path.push("cache");
+    path.push(token_file_name(sso));
+    path
+}
+
+// トークンファイルの名前
+// `sso-session`を使用している場合はセッション名、それ以外は開始URLのSHA1がファイル名となる
+fn token_file_name(sso: &SsoConfig) -> String {
+    let key = sso.session_name.as_ref().unwrap_or(&sso.start_url);
+    format!("{:x}.json", Sha1::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sso_config(session_name: Option<&str>) -> SsoConfig {
+        SsoConfig {
+            session_name: session_name.map(|s| s.to_string()),
+            start_url: "https://my-sso-portal.awsapps.com/start".to_string(),
+            region: "us-east-1".to_string(),
+            account_id: "123456789012".to_string(),
+            role_name: "ReadOnly".to_string(),
+        }
+    }
+
+    // テストごとに一時ディレクトリにトークンファイルを作成する
+    fn write_token(name: &str, expires_at: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("s3-explorer-sso-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let text = format!(
+            r#"{{"accessToken": "token-{}", "expiresAt": "{}", "region": "us-east-1"}}"#,
+            name, expires_at
+        );
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn is_expired(err: &CredentialsError) -> bool {
+        crate::error::find_source::<SsoTokenExpired>(err).is_some()
+    }
+
+    fn time_of(value: &str) -> SystemTime {
+        chrono::DateTime::parse_from_rfc3339(value).unwrap().into()
+    }
+
+    #[test]
+    fn file_name_is_sha1_of_start_url() {
+        assert_eq!(
+            token_file_name(&sso_config(None)),
+            "c7aaaf71fcc8777ae2475525ed049d39fe16c484.json"
+        );
+    }
+
+    #[test]
+    fn file_name_is_sha1_of_session_name() {
+        assert_eq!(
+            token_file_name(&sso_config(Some("my-sso"))),
+            "0ad374308c5a4e22f723adf10145eafad7c4031c.json"
+        );
+    }
+
+    #[test]
+    fn valid_token_is_returned() {
+        let path = write_token("valid", "2024-01-01T12:00:00Z");
+        let now = time_of("2024-01-01T11:00:00Z");
+        assert_eq!(read_token_file("dev", &path, now).unwrap(), "token-valid");
+    }
+
+    #[test]
+    fn legacy_utc_suffix_is_parsed() {
+        let path = write_token("legacy", "2024-01-01T12:00:00UTC");
+        let now = time_of("2024-01-01T11:00:00Z");
+        assert_eq!(read_token_file("dev", &path, now).unwrap(), "token-legacy");
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let path = write_token("expired", "2024-01-01T12:00:00Z");
+        let now = time_of("2024-01-01T12:00:00Z");
+        let err = read_token_file("dev", &path, now).unwrap_err();
+        assert!(is_expired(&err));
+    }
+
+    #[test]
+    fn missing_token_requires_login() {
+        let path = std::env::temp_dir().join("s3-explorer-sso-missing.json");
+        let err = read_token_file("dev", &path, SystemTime::now()).unwrap_err();
+        assert!(is_expired(&err));
+    }
+}
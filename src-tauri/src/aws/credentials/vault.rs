@@ -0,0 +1,312 @@
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use aes_gcm::{
+    aead::{Aead, NewAead},
+    Aes256Gcm, Key, Nonce,
+};
+use aws_types::{
+    credentials::{self, CredentialsError},
+    Credentials,
+};
+use hmac::Hmac;
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+// ファイル形式のバージョン
+const VAULT_VERSION: u32 = 1;
+
+// 鍵導出の繰り返し回数
+const PBKDF2_ROUNDS: u32 = 210_000;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+// ロック解除中の保管庫(アプリ起動中のみメモリ上に保持する)
+static UNLOCKED: Lazy<Mutex<Option<Unlocked>>> = Lazy::new(|| Mutex::new(None));
+
+/// 保管庫がロックされているため認証情報を取得できない
+#[derive(Debug)]
+pub struct VaultLocked {
+    pub profile: String,
+}
+
+impl std::fmt::Display for VaultLocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The credentials for profile `{}` are stored in the vault. Unlock the vault with the master password.",
+            self.profile
+        )
+    }
+}
+
+impl std::error::Error for VaultLocked {}
+
+/// 保管庫に保存する認証情報
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct VaultCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    #[serde(default)]
+    pub session_token: Option<String>,
+}
+
+impl Drop for VaultCredentials {
+    fn drop(&mut self) {
+        self.secret_access_key.zeroize();
+        self.session_token.zeroize();
+    }
+}
+
+/// 保管庫の状態
+#[derive(serde::Serialize)]
+pub struct VaultStatus {
+    pub exists: bool,
+    pub unlocked: bool,
+    // 保管庫に認証情報を保存しているプロファイル
+    pub profiles: Vec<String>,
+}
+
+// 保管庫のファイル
+// プロファイル名はロック中でも参照できるように平文で保存し、認証情報のみ暗号化する
+#[derive(serde::Serialize, serde::Deserialize)]
+struct VaultFile {
+    version: u32,
+    // Base64
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+    profiles: Vec<String>,
+}
+
+// ロック解除中の鍵と認証情報
+struct Unlocked {
+    key: [u8; 32],
+    salt: Vec<u8>,
+    items: HashMap<String, VaultCredentials>,
+}
+
+impl Drop for Unlocked {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+/// 保管庫の状態を取得する
+pub fn status() -> VaultStatus {
+    VaultStatus {
+        exists: vault_path().exists(),
+        unlocked: UNLOCKED.lock().unwrap().is_some(),
+        profiles: profile_names(),
+    }
+}
+
+/// 指定のマスターパスワードで保管庫を新規作成し、ロックを解除する
+/// 既存の保管庫を空の保管庫で上書きしないよう、存在する場合はエラーとする
+pub async fn create(password: &str) -> Result<(), String> {
+    if password.is_empty() {
+        return Err("master password must not be empty".to_string());
+    }
+    if vault_path().exists() {
+        return Err("vault already exists".to_string());
+    }
+
+    let mut salt = vec![0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let unlocked = Unlocked {
+        key: derive_key(password, &salt).await?,
+        salt,
+        items: HashMap::new(),
+    };
+    save(&unlocked)?;
+    *UNLOCKED.lock().unwrap() = Some(unlocked);
+    Ok(())
+}
+
+/// マスターパスワードで保管庫のロックを解除する
+/// 保管庫が存在しない場合はエラー(`create`で作成する)
+pub async fn unlock(password: &str) -> Result<(), String> {
+    if password.is_empty() {
+        return Err("master password must not be empty".to_string());
+    }
+    if !vault_path().exists() {
+        return Err("vault does not exist".to_string());
+    }
+
+    let file = read_file()?;
+    let salt = decode(&file.salt)?;
+    // 編集されたファイルなどで長さが異なる場合は復号できない
+    let nonce = decode(&file.nonce)?;
+    if nonce.len() != NONCE_LEN {
+        return Err("vault file is corrupted: invalid nonce".to_string());
+    }
+    let key = derive_key(password, &salt).await?;
+
+    let cipher = Aes256Gcm::new(Key::from_slice(&key));
+    let mut plain = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            decode(&file.ciphertext)?.as_ref(),
+        )
+        .map_err(|_| "incorrect master password".to_string())?;
+    let items = serde_json::from_slice::<HashMap<String, VaultCredentials>>(&plain)
+        .map_err(|err| err.to_string());
+    plain.zeroize();
+
+    *UNLOCKED.lock().unwrap() = Some(Unlocked {
+        key,
+        salt,
+        items: items?,
+    });
+    Ok(())
+}
+
+/// 保管庫をロックしてメモリ上の認証情報を破棄する
+pub fn lock() {
+    UNLOCKED.lock().unwrap().take();
+}
+
+/// マスターパスワードを変更する
+pub async fn change_password(current: &str, new_password: &str) -> Result<(), String> {
+    if new_password.is_empty() {
+        return Err("master password must not be empty".to_string());
+    }
+    unlock(current).await?;
+
+    let mut salt = vec![0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let key = derive_key(new_password, &salt).await?;
+
+    let mut guard = UNLOCKED.lock().unwrap();
+    let unlocked = guard.as_mut().ok_or("vault is locked")?;
+    unlocked.key = key;
+    unlocked.salt = salt;
+    save(unlocked)
+}
+
+/// プロファイルの認証情報を保管庫に保存する(ロック解除中のみ)
+pub fn set_credentials(profile: &str, cred: VaultCredentials) -> Result<(), String> {
+    if profile.trim().is_empty() {
+        return Err("profile name must not be empty".to_string());
+    }
+    if cred.access_key_id.trim().is_empty() || cred.secret_access_key.trim().is_empty() {
+        return Err("access key id and secret access key are required".to_string());
+    }
+
+    let mut guard = UNLOCKED.lock().unwrap();
+    let unlocked = guard.as_mut().ok_or("vault is locked")?;
+    unlocked.items.insert(profile.to_string(), cred);
+    save(unlocked)
+}
+
+/// プロファイルの認証情報を保管庫から削除する(ロック解除中のみ)
+pub fn delete_credentials(profile: &str) -> Result<(), String> {
+    let mut guard = UNLOCKED.lock().unwrap();
+    let unlocked = guard.as_mut().ok_or("vault is locked")?;
+    if unlocked.items.remove(profile).is_some() {
+        save(unlocked)?;
+    }
+    Ok(())
+}
+
+/// 保管庫に認証情報を保存しているプロファイル名
+pub fn profile_names() -> Vec<String> {
+    if let Some(unlocked) = UNLOCKED.lock().unwrap().as_ref() {
+        let mut names = unlocked.items.keys().cloned().collect::<Vec<String>>();
+        names.sort();
+        return names;
+    }
+    read_file().map(|f| f.profiles).unwrap_or_default()
+}
+
+/// 保管庫に認証情報を保存しているプロファイルか
+pub fn contains(profile: &str) -> bool {
+    profile_names().iter().any(|n| n == profile)
+}
+
+/// 保管庫から認証情報を取得する
+/// 保管庫に存在しないプロファイルの場合は`None`
+pub fn credentials(profile: &str) -> Option<credentials::Result> {
+    if let Some(unlocked) = UNLOCKED.lock().unwrap().as_ref() {
+        return unlocked.items.get(profile).map(|c| {
+            Ok(Credentials::new(
+                c.access_key_id.clone(),
+                c.secret_access_key.clone(),
+                c.session_token.clone(),
+                None,
+                "Vault",
+            ))
+        });
+    }
+
+    // ロック中の場合は保存されているプロファイルのみエラーとする
+    if contains(profile) {
+        return Some(Err(CredentialsError::not_loaded(VaultLocked {
+            profile: profile.to_string(),
+        })));
+    }
+    None
+}
+
+// パスワードから暗号化の鍵を導出する
+// 計算に時間がかかるため、非同期のランタイムをブロックしないよう専用のスレッドで実行する
+async fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let mut password = password.to_string();
+    let salt = salt.to_vec();
+    tokio::task::spawn_blocking(move || {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), &salt, PBKDF2_ROUNDS, &mut key);
+        password.zeroize();
+        key
+    })
+    .await
+    .map_err(|err| err.to_string())
+}
+
+// 認証情報を暗号化してファイルに書き込む
+fn save(unlocked: &Unlocked) -> Result<(), String> {
+    let mut plain = serde_json::to_vec(&unlocked.items).map_err(|err| err.to_string())?;
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = Aes256Gcm::new(Key::from_slice(&unlocked.key));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plain.as_ref())
+        .map_err(|_| "failed to encrypt the vault".to_string());
+    plain.zeroize();
+
+    let mut profiles = unlocked.items.keys().cloned().collect::<Vec<String>>();
+    profiles.sort();
+    let file = VaultFile {
+        version: VAULT_VERSION,
+        salt: base64::encode(&unlocked.salt),
+        nonce: base64::encode(nonce),
+        ciphertext: base64::encode(ciphertext?),
+        profiles,
+    };
+    let text = serde_json::to_string_pretty(&file).map_err(|err| err.to_string())?;
+
+    // 本人のみ読み書きできる権限で、一時ファイルを経由して置き換える
+    crate::config::write_private(&vault_path(), text.as_bytes()).map_err(|err| err.to_string())
+}
+
+// 保管庫のファイルを読み込む
+fn read_file() -> Result<VaultFile, String> {
+    let text = std::fs::read_to_string(vault_path()).map_err(|err| err.to_string())?;
+    let file: VaultFile = serde_json::from_str(&text).map_err(|err| err.to_string())?;
+    if file.version != VAULT_VERSION {
+        return Err(format!("unsupported vault version: {}", file.version));
+    }
+    Ok(file)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, String> {
+    base64::decode(value).map_err(|err| err.to_string())
+}
+
+// 保管庫のファイルのパス
+fn vault_path() -> PathBuf {
+    crate::config::data_dir().join("vault.json")
+}
@@ -0,0 +1,510 @@
+use std::{error::Error, path::PathBuf};
+
+use super::{
+    credentials::{sources, vault},
+    parser::{self, FileKind, Section},
+    profile,
+};
+use crate::config;
+
+// ファイルごとに残すバックアップの数
+const MAX_BACKUPS: usize = 10;
+
+/// 画面から入力されたプロファイルの設定
+/// 未指定(または空文字)の項目はファイルから削除する
+#[derive(Clone, Default, serde::Deserialize)]
+pub struct ProfileInput {
+    pub region: Option<String>,
+    pub output: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+    pub role_arn: Option<String>,
+    pub source_profile: Option<String>,
+    pub external_id: Option<String>,
+    pub role_session_name: Option<String>,
+    pub duration_seconds: Option<i32>,
+    pub mfa_serial: Option<String>,
+}
+
+impl ProfileInput {
+    // configファイルに書き込む値
+    fn config_values(&self) -> Vec<(&'static str, Option<String>)> {
+        vec![
+            ("region", self.region.clone()),
+            ("output", self.output.clone()),
+            ("role_arn", self.role_arn.clone()),
+            ("source_profile", self.source_profile.clone()),
+            ("external_id", self.external_id.clone()),
+            ("role_session_name", self.role_session_name.clone()),
+            (
+                "duration_seconds",
+                self.duration_seconds.map(|d| d.to_string()),
+            ),
+            ("mfa_serial", self.mfa_serial.clone()),
+        ]
+    }
+
+    // credentialファイルに書き込む値
+    fn credential_values(&self) -> Vec<(&'static str, Option<String>)> {
+        vec![
+            ("aws_access_key_id", self.access_key_id.clone()),
+            ("aws_secret_access_key", self.secret_access_key.clone()),
+            ("aws_session_token", self.session_token.clone()),
+        ]
+    }
+
+    // 書き込む値が一つもないか
+    fn is_empty(&self) -> bool {
+        self.config_values()
+            .into_iter()
+            .chain(self.credential_values())
+            .all(|(_, v)| v.filter(|v| !v.trim().is_empty()).is_none())
+    }
+}
+
+/// プロファイルを新規作成する
+/// 値が一つも指定されていない場合はファイルに書き込めないためエラー
+pub fn create_profile(name: &str, input: &ProfileInput) -> Result<(), Box<dyn Error>> {
+    validate_name(name)?;
+    if input.is_empty() {
+        return Err("profile must have at least one value".into());
+    }
+
+    let mut conf = Document::load(FileKind::Config)?;
+    let mut cred = Document::load(FileKind::Credentials)?;
+    if conf.has_profile(name) || cred.has_profile(name) {
+        return Err(format!("profile `{}` already exists", name).into());
+    }
+
+    write_values(&mut conf, name, input.config_values())?;
+    write_values(&mut cred, name, input.credential_values())?;
+    conf.save()?;
+    cred.save()
+}
+
+/// プロファイルの設定を更新する
+pub fn update_profile(name: &str, input: &ProfileInput) -> Result<(), Box<dyn Error>> {
+    let mut conf = Document::load(FileKind::Config)?;
+    let mut cred = Document::load(FileKind::Credentials)?;
+    if !conf.has_profile(name) && !cred.has_profile(name) {
+        return Err(format!("profile `{}` does not exist", name).into());
+    }
+
+    write_values(&mut conf, name, input.config_values())?;
+    write_values(&mut cred, name, input.credential_values())?;
+    conf.save()?;
+    cred.save()
+}
+
+/// プロファイル名を変更する
+/// 他のプロファイルの`source_profile`やアプリの設定で参照している名称も合わせて変更する
+pub fn rename_profile(old_name: &str, new_name: &str) -> Result<(), Box<dyn Error>> {
+    validate_name(new_name)?;
+
+    let mut conf = Document::load(FileKind::Config)?;
+    let mut cred = Document::load(FileKind::Credentials)?;
+    if !conf.has_profile(old_name) && !cred.has_profile(old_name) {
+        return Err(format!("profile `{}` does not exist", old_name).into());
+    }
+    if conf.has_profile(new_name) || cred.has_profile(new_name) {
+        return Err(format!("profile `{}` already exists", new_name).into());
+    }
+
+    conf.rename_profile(old_name, new_name)?;
+    cred.rename_profile(old_name, new_name)?;
+    conf.replace_values("source_profile", old_name, new_name)?;
+    conf.save()?;
+    cred.save()?;
+
+    // 選択中のプロファイルとお気に入りの参照先を更新
+    let mut user_config = config::read_config();
+    if user_config.profile.as_deref() == Some(old_name) {
+        user_config.profile = Some(new_name.to_string());
+    }
+    if let Some(favorites) = user_config.favorites.as_mut() {
+        if let Some(list) = favorites.items.remove(old_name) {
+            favorites.items.insert(new_name.to_string(), list);
+        }
+    }
+    user_config.write_to_file();
+
+    Ok(())
+}
+
+/// プロファイルを削除する
+/// 保管庫の認証情報やお気に入りなど、プロファイルに紐づくアプリの設定も削除する
+pub fn delete_profile(name: &str) -> Result<(), Box<dyn Error>> {
+    let mut conf = Document::load(FileKind::Config)?;
+    let mut cred = Document::load(FileKind::Credentials)?;
+    if !conf.has_profile(name) && !cred.has_profile(name) {
+        return Err(format!("profile `{}` does not exist", name).into());
+    }
+
+    // 保管庫の認証情報はロック中は削除できないため、ファイルを変更する前に確認する
+    let in_vault = vault::contains(name);
+    if in_vault && !vault::status().unlocked {
+        return Err(format!(
+            "unlock the vault to delete the credentials of profile `{}`",
+            name
+        )
+        .into());
+    }
+
+    conf.delete_profile(name)?;
+    cred.delete_profile(name)?;
+    conf.save()?;
+    cred.save()?;
+
+    if in_vault {
+        vault::delete_credentials(name)?;
+    }
+
+    // 選択中のプロファイルとお気に入りを削除
+    let mut user_config = config::read_config();
+    if user_config.profile.as_deref() == Some(name) {
+        user_config.profile = None;
+    }
+    if let Some(favorites) = user_config.favorites.as_mut() {
+        favorites.items.remove(name);
+    }
+    user_config.write_to_file();
+
+    Ok(())
+}
+
+// 指定の値をファイルに反映する
+fn write_values(
+    doc: &mut Document,
+    name: &str,
+    values: Vec<(&'static str, Option<String>)>,
+) -> Result<(), Box<dyn Error>> {
+    for (key, value) in values {
+        let value = value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        if let Some(v) = &value {
+            if v.contains('\n') || v.contains('\r') {
+                return Err(format!("value of `{}` must be a single line", key).into());
+            }
+        }
+        doc.set(name, key, value.as_deref())?;
+    }
+    Ok(())
+}
+
+// プロファイル名として使用できるか確認する
+fn validate_name(name: &str) -> Result<(), Box<dyn Error>> {
+    if name.trim().is_empty() || name.trim() != name {
+        return Err("profile name must not be empty or start/end with whitespace".into());
+    }
+    if name.contains(|c: char| c == '[' || c == ']' || c == '#' || c == ';' || c.is_control()) {
+        return Err(format!("profile name `{}` contains invalid characters", name).into());
+    }
+    // 疑似プロファイルと区別するため
+    if name.starts_with(sources::PSEUDO_PROFILE_PREFIX) {
+        return Err(format!(
+            "profile name must not start with `{}`",
+            sources::PSEUDO_PROFILE_PREFIX
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// 編集中のファイル
+// コメントや記述順、未知のキーを保持するため行単位で編集する
+struct Document {
+    kind: FileKind,
+    path: PathBuf,
+    lines: Vec<String>,
+    // 読み込んだファイルの改行コード(書き込み時も同じものを使用する)
+    newline: &'static str,
+    sections: Vec<Section>,
+    changed: bool,
+}
+
+impl Document {
+    // ファイルを読み込む(存在しない場合は空として扱う)
+    fn load(kind: FileKind) -> Result<Self, Box<dyn Error>> {
+        let path = profile::file_path(kind).ok_or("Oops... does not home directory..orz")?;
+        let text = if path.exists() {
+            std::fs::read_to_string(&path)?
+        } else {
+            String::new()
+        };
+        let sections = parser::parse(&text)?;
+
+        Ok(Document {
+            kind,
+            path,
+            lines: text.lines().map(|l| l.to_string()).collect(),
+            newline: newline_of(&text),
+            sections,
+            changed: false,
+        })
+    }
+
+    // 編集後に各セクションの行番号を更新する
+    fn reparse(&mut self) -> Result<(), Box<dyn Error>> {
+        self.sections = parser::parse(&self.lines.join("\n"))?;
+        self.changed = true;
+        Ok(())
+    }
+
+    // 指定のプロファイルのセクションを取得
+    fn profile_sections(&self, name: &str) -> Vec<Section> {
+        self.sections
+            .iter()
+            .filter(|s| parser::profile_name(&s.header, self.kind).as_deref() == Some(name))
+            .cloned()
+            .collect()
+    }
+
+    fn has_profile(&self, name: &str) -> bool {
+        !self.profile_sections(name).is_empty()
+    }
+
+    // セクションのヘッダ行を生成
+    fn header(&self, name: &str) -> String {
+        match self.kind {
+            FileKind::Config if name != "default" => format!("[profile {}]", name),
+            _ => format!("[{}]", name),
+        }
+    }
+
+    // プロパティを設定する(`None`の場合は削除)
+    fn set(&mut self, name: &str, key: &str, value: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let sections = self.profile_sections(name);
+        let mut found = sections
+            .iter()
+            .flat_map(|s| s.properties.iter())
+            .filter(|p| p.key == key)
+            .cloned()
+            .collect::<Vec<parser::Property>>();
+
+        match value {
+            Some(v) => {
+                let line = format!("{} = {}", key, v);
+                match found.pop() {
+                    // 既に存在する場合は最後に定義されたものを置き換える
+                    Some(p) => {
+                        if p.value == v && p.sub_properties.is_empty() {
+                            return Ok(());
+                        }
+                        self.lines.splice((p.line - 1)..p.end_line, vec![line]);
+                    }
+                    // 存在しない場合はセクションの最後に追加
+                    None => match sections.last() {
+                        Some(s) => self.lines.insert(s.end_line, line),
+                        None => {
+                            if self.lines.last().map_or(false, |l| !l.trim().is_empty()) {
+                                self.lines.push(String::new());
+                            }
+                            let header = self.header(name);
+                            self.lines.push(header);
+                            self.lines.push(line);
+                        }
+                    },
+                }
+            }
+            None => {
+                if found.is_empty() {
+                    return Ok(());
+                }
+                // 後ろの行から削除する
+                found.sort_by_key(|p| p.line);
+                for p in found.iter().rev() {
+                    self.lines.drain((p.line - 1)..p.end_line);
+                }
+            }
+        }
+        self.reparse()
+    }
+
+    // 指定のキーの値が一致するものを置き換える
+    fn replace_values(&mut self, key: &str, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+        let targets = self
+            .sections
+            .iter()
+            .flat_map(|s| s.properties.iter())
+            .filter(|p| p.key == key && p.value == from && p.sub_properties.is_empty())
+            .map(|p| p.line)
+            .collect::<Vec<usize>>();
+        if targets.is_empty() {
+            return Ok(());
+        }
+        for line in targets {
+            self.lines[line - 1] = format!("{} = {}", key, to);
+        }
+        self.reparse()
+    }
+
+    // プロファイル名を変更する
+    fn rename_profile(&mut self, old_name: &str, new_name: &str) -> Result<(), Box<dyn Error>> {
+        let sections = self.profile_sections(old_name);
+        if sections.is_empty() {
+            return Ok(());
+        }
+        let header = self.header(new_name);
+        for s in sections {
+            self.lines[s.line - 1] = header.clone();
+        }
+        self.reparse()
+    }
+
+    // プロファイルのセクションを削除する
+    fn delete_profile(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let sections = self.profile_sections(name);
+        if sections.is_empty() {
+            return Ok(());
+        }
+        for s in sections.iter().rev() {
+            self.lines.drain((s.line - 1)..s.end_line);
+        }
+        self.reparse()
+    }
+
+    // バックアップを作成してからファイルに書き込む
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        if self.path.exists() {
+            self.backup()?;
+        } else if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut text = self.lines.join(self.newline);
+        text.push_str(self.newline);
+
+        // 本人のみ読み書きできる権限で、一時ファイルを経由して置き換える
+        config::write_private(&self.path, text.as_bytes())?;
+        Ok(())
+    }
+
+    // 編集前のファイルを`{ファイル名}.{日時}.bak`にコピーする
+    // 同じ日時のバックアップが存在する場合は連番を付与し、古いものから`MAX_BACKUPS`を超えた分を削除する
+    fn backup(&self) -> Result<(), Box<dyn Error>> {
+        let file_name = self
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let stamp = chrono::Local::now().format("%Y%m%d%H%M%S%3f").to_string();
+        let mut backup = self
+            .path
+            .with_file_name(format!("{}.{}.bak", file_name, stamp));
+        let mut seq = 1;
+        while backup.exists() {
+            backup = self
+                .path
+                .with_file_name(format!("{}.{}-{}.bak", file_name, stamp, seq));
+            seq += 1;
+        }
+        std::fs::copy(&self.path, &backup)?;
+
+        // 日時の順に並べ替え、古いものを削除する
+        let dir = match self.path.parent() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        let prefix = format!("{}.", file_name);
+        let mut backups = std::fs::read_dir(dir)?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let name = e.file_name().to_str()?.to_string();
+                let stamp = name.strip_prefix(&prefix)?.strip_suffix(".bak")?;
+                backup_order(stamp).map(|order| (order, e.path()))
+            })
+            .collect::<Vec<((String, u32), PathBuf)>>();
+        backups.sort();
+        let expired = backups.len().saturating_sub(MAX_BACKUPS);
+        for (_, path) in backups.into_iter().take(expired) {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+// ファイルの改行コード(CRLFを含む場合はCRLF)
+fn newline_of(text: &str) -> &'static str {
+    if text.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+// バックアップの日時部分から並び順を取得する(`{日時}`または`{日時}-{連番}`)
+// アプリが作成したバックアップでない場合は`None`
+fn backup_order(stamp: &str) -> Option<(String, u32)> {
+    let (time, seq) = match stamp.split_once('-') {
+        Some((t, s)) => (t, s.parse::<u32>().ok()?),
+        None => (stamp, 0),
+    };
+    if time.is_empty() || !time.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((time.to_string(), seq))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_input_is_detected() {
+        assert!(ProfileInput::default().is_empty());
+        assert!(ProfileInput {
+            region: Some(" ".to_string()),
+            ..Default::default()
+        }
+        .is_empty());
+        assert!(!ProfileInput {
+            duration_seconds: Some(3600),
+            ..Default::default()
+        }
+        .is_empty());
+    }
+
+    #[test]
+    fn newline_follows_file() {
+        assert_eq!(newline_of("[default]\r\nregion = us-east-1\r\n"), "\r\n");
+        assert_eq!(newline_of("[default]\nregion = us-east-1\n"), "\n");
+        assert_eq!(newline_of(""), "\n");
+    }
+
+    #[test]
+    fn backups_are_ordered_by_time_and_sequence() {
+        let mut list = [
+            backup_order("20240101000000123-1").unwrap(),
+            backup_order("20240101000000123-10").unwrap(),
+            backup_order("20240101000000123-2").unwrap(),
+            backup_order("20240101000000123").unwrap(),
+            backup_order("20231231235959").unwrap(),
+        ];
+        list.sort();
+        assert_eq!(
+            list.iter()
+                .map(|(t, s)| format!("{}-{}", t, s))
+                .collect::<Vec<String>>(),
+            vec![
+                "20231231235959-0",
+                "20240101000000123-0",
+                "20240101000000123-1",
+                "20240101000000123-2",
+                "20240101000000123-10",
+            ]
+        );
+    }
+
+    #[test]
+    fn other_files_are_not_backups() {
+        assert_eq!(backup_order("old"), None);
+        assert_eq!(backup_order("20240101-x"), None);
+        assert_eq!(backup_order(""), None);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use aws_smithy_client::{
+    erase::{DynConnector, DynMiddleware},
+    hyper_ext::Adapter,
+};
+use aws_smithy_http::{body::SdkBody, endpoint::EndpointPrefix, operation};
+use aws_types::SdkConfig;
+
+use crate::config::connection::{AddressingStyle, Connection};
+
+/// 署名の計算に使用するダミーの認証情報
+/// 匿名アクセスでは送信前に署名を除去するため値は使用されない
+pub fn anonymous_credentials() -> aws_types::Credentials {
+    aws_types::Credentials::new("anonymous", "anonymous", None, None, "Anonymous")
+}
+
+/// 接続設定に応じたクライアントを生成する
+/// このSDKのリクエストはパス形式(`/bucket/key`)となるため、
+/// 仮想ホスト形式を指定した場合は署名の前にバケット名をホスト名に移動する
+pub fn s3_client(
+    config: &SdkConfig,
+    conn: &Connection,
+    region_name: Option<String>,
+) -> aws_sdk_s3::Client {
+    let mut builder = aws_sdk_s3::config::Builder::from(config);
+
+    // バケットのリージョンが異なる場合
+    if let Some(r) = region_name {
+        builder = builder.region(aws_sdk_s3::Region::new(r));
+    }
+
+    if let Some(uri) = endpoint_uri(conn) {
+        // 仮想ホスト形式ではホスト名の先頭にバケット名を付与するため、変更可能なエンドポイントとする
+        let endpoint = match conn.addressing_style {
+            AddressingStyle::Path => aws_sdk_s3::Endpoint::immutable(uri),
+            AddressingStyle::VirtualHosted => aws_sdk_s3::Endpoint::mutable(uri),
+        };
+        builder = builder.endpoint_resolver(endpoint);
+    }
+    let conf = builder.build();
+
+    // デフォルトのコネクタで問題ない場合
+    if conn.verify_tls && !conn.anonymous && conn.addressing_style == AddressingStyle::Path {
+        return aws_sdk_s3::Client::from_conf(conf);
+    }
+
+    // TLS証明書の検証を行わない場合は専用のコネクタを使用する
+    let connector = if conn.verify_tls {
+        DynConnector::new(Adapter::builder().build(aws_smithy_client::conns::https()))
+    } else {
+        DynConnector::new(Adapter::builder().build(insecure_connector()))
+    };
+    let connector = if conn.anonymous {
+        DynConnector::new(Unsigned { inner: connector })
+    } else {
+        connector
+    };
+
+    match conn.addressing_style {
+        AddressingStyle::Path => aws_sdk_s3::Client::from_conf_conn(conf, connector),
+        AddressingStyle::VirtualHosted => {
+            // SDKのミドルウェア(エンドポイントの解決、署名)より前に変換する
+            let middleware = tower::layer::util::Stack::new(
+                aws_sdk_s3::middleware::DefaultMiddleware::new(),
+                VirtualHostedLayer,
+            );
+            let client = aws_smithy_client::Builder::new()
+                .connector(connector)
+                .middleware(DynMiddleware::new(middleware))
+                .build();
+            aws_sdk_s3::Client::with_config(client, conf)
+        }
+    }
+}
+
+/// 接続設定に応じたSTSのクライアントを生成する
+/// S3互換のエンドポイントの場合は同じエンドポイントにリクエストする
+pub fn sts_client(config: &SdkConfig, conn: &Connection) -> aws_sdk_sts::Client {
+    let mut builder = aws_sdk_sts::config::Builder::from(config);
+    if let Some(uri) = endpoint_uri(conn) {
+        builder = builder.endpoint_resolver(aws_sdk_sts::Endpoint::immutable(uri));
+    }
+    let conf = builder.build();
+
+    if conn.verify_tls {
+        aws_sdk_sts::Client::from_conf(conf)
+    } else {
+        let connector = DynConnector::new(Adapter::builder().build(insecure_connector()));
+        aws_sdk_sts::Client::from_conf_conn(conf, connector)
+    }
+}
+
+// エンドポイントのURLを解析する
+fn endpoint_uri(conn: &Connection) -> Option<http::Uri> {
+    let url = conn.endpoint_url.as_ref()?;
+    match url.trim().parse::<http::Uri>() {
+        Ok(uri) => Some(uri),
+        Err(err) => {
+            println!("invalid endpoint url `{}`: {}", url, err);
+            None
+        }
+    }
+}
+
+// TLS証明書の検証を行わないコネクタ
+fn insecure_connector() -> hyper_rustls::HttpsConnector<hyper::client::HttpConnector> {
+    let mut tls = rustls::ClientConfig::new();
+    tls.dangerous()
+        .set_certificate_verifier(Arc::new(NoCertificateVerification));
+
+    let mut http = hyper::client::HttpConnector::new();
+    http.enforce_http(false);
+    hyper_rustls::HttpsConnector::from((http, tls))
+}
+
+// すべての証明書を受け入れる検証処理
+struct NoCertificateVerification;
+
+impl rustls::ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _roots: &rustls::RootCertStore,
+        _presented_certs: &[rustls::Certificate],
+        _dns_name: webpki::DNSNameRef<'_>,
+        _ocsp_response: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+// 送信直前に署名を除去し、匿名のリクエストとするコネクタ
+// ページングを含むすべての操作に適用するためコネクタで処理する
+#[derive(Clone)]
+struct Unsigned {
+    inner: DynConnector,
+}
+
+impl tower::Service<http::Request<SdkBody>> for Unsigned {
+    type Response = <DynConnector as tower::Service<http::Request<SdkBody>>>::Response;
+    type Error = <DynConnector as tower::Service<http::Request<SdkBody>>>::Error;
+    type Future = <DynConnector as tower::Service<http::Request<SdkBody>>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<SdkBody>) -> Self::Future {
+        let headers = req.headers_mut();
+        headers.remove(http::header::AUTHORIZATION);
+        headers.remove("x-amz-security-token");
+        self.inner.call(req)
+    }
+}
+
+// 仮想ホスト形式に変換するミドルウェア
+#[derive(Clone, Copy, Debug)]
+struct VirtualHostedLayer;
+
+impl<S> tower::Layer<S> for VirtualHostedLayer {
+    type Service = VirtualHosted<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VirtualHosted { inner }
+    }
+}
+
+#[derive(Clone)]
+struct VirtualHosted<S> {
+    inner: S,
+}
+
+impl<S> tower::Service<operation::Request> for VirtualHosted<S>
+where
+    S: tower::Service<operation::Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: operation::Request) -> Self::Future {
+        if let Some((bucket, uri)) = split_bucket(req.http().uri()) {
+            if let Ok(prefix) = EndpointPrefix::new(format!("{}.", bucket)) {
+                *req.http_mut().uri_mut() = uri;
+                req.properties_mut().insert(prefix);
+            }
+        }
+        self.inner.call(req)
+    }
+}
+
+// パス形式のURIからバケット名を取り出し、バケット名を除いたURIを返却する
+// ホスト名に使用できないバケット名(大文字、`_`、`.`を含むなど)の場合はパス形式のまま送信する
+fn split_bucket(uri: &http::Uri) -> Option<(String, http::Uri)> {
+    let path = uri.path().strip_prefix('/')?;
+    let (bucket, rest) = match path.split_once('/') {
+        Some((bucket, rest)) => (bucket, rest),
+        None => (path, ""),
+    };
+    let valid = (3..=63).contains(&bucket.len())
+        && bucket
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !bucket.starts_with('-')
+        && !bucket.ends_with('-');
+    if !valid {
+        return None;
+    }
+
+    let path_and_query = match uri.query() {
+        Some(q) => format!("/{}?{}", rest, q),
+        None => format!("/{}", rest),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    let uri = http::Uri::from_parts(parts).ok()?;
+    Some((bucket.to_string(), uri))
+}
@@ -0,0 +1,117 @@
+use std::time::SystemTime;
+
+use aws_types::Credentials;
+
+use super::session::Session;
+use crate::error::aws_error::{AwsError, AwsErrorKind};
+
+/// 認証情報の出力形式
+#[derive(Clone, Copy, Debug, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Bash,
+    Zsh,
+    Fish,
+    // `credential_process`の出力形式のJSON
+    CredentialProcess,
+    // `.env`ファイル
+    Dotenv,
+}
+
+// `credential_process`の出力
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessOutput<'a> {
+    version: i64,
+    access_key_id: &'a str,
+    secret_access_key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_token: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expiration: Option<String>,
+}
+
+/// セッションで使用している認証情報を指定の形式で出力する
+/// AssumeRoleなどで取得した一時認証情報もそのまま出力する
+pub async fn export_credentials(
+    session: &Session,
+    format: ExportFormat,
+) -> Result<String, AwsError> {
+    // 匿名アクセスの場合は出力する認証情報が存在しない
+    if session.connection.as_ref().map_or(false, |c| c.anonymous) {
+        return Err(AwsError::new(AwsErrorKind::AccessDenied));
+    }
+    let cred = session
+        .credentials()
+        .await?
+        .ok_or_else(|| AwsError::new(AwsErrorKind::AccessDenied))?;
+
+    if let ExportFormat::CredentialProcess = format {
+        let output = ProcessOutput {
+            version: 1,
+            access_key_id: cred.access_key_id(),
+            secret_access_key: cred.secret_access_key(),
+            session_token: cred.session_token(),
+            expiration: cred.expiry().map(rfc3339),
+        };
+        return Ok(serde_json::to_string_pretty(&output).unwrap());
+    }
+
+    let lines = variables(session, &cred)
+        .into_iter()
+        .map(|(key, value)| match format {
+            ExportFormat::Bash | ExportFormat::Zsh => {
+                format!("export {}='{}'", key, value.replace('\'', "'\\''"))
+            }
+            ExportFormat::Fish => format!(
+                "set -gx {} '{}'",
+                key,
+                value.replace('\\', "\\\\").replace('\'', "\\'")
+            ),
+            _ => format!(
+                "{}=\"{}\"",
+                key,
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            ),
+        })
+        .collect::<Vec<String>>();
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    Ok(text)
+}
+
+// 出力する環境変数
+fn variables(session: &Session, cred: &Credentials) -> Vec<(&'static str, String)> {
+    let mut vars = vec![
+        ("AWS_ACCESS_KEY_ID", cred.access_key_id().to_string()),
+        (
+            "AWS_SECRET_ACCESS_KEY",
+            cred.secret_access_key().to_string(),
+        ),
+    ];
+    if let Some(token) = cred.session_token() {
+        vars.push(("AWS_SESSION_TOKEN", token.to_string()));
+    }
+    if let Some(expiry) = cred.expiry() {
+        vars.push(("AWS_CREDENTIAL_EXPIRATION", rfc3339(expiry)));
+    }
+    if let Some(region) = session.region() {
+        vars.push(("AWS_REGION", region.clone()));
+        vars.push(("AWS_DEFAULT_REGION", region));
+    }
+    // S3互換のエンドポイントの場合はCLIからも同じエンドポイントに接続する
+    if let Some(url) = session
+        .connection
+        .as_ref()
+        .and_then(|c| c.endpoint_url.clone())
+    {
+        vars.push(("AWS_ENDPOINT_URL", url));
+    }
+    vars
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    let d: chrono::DateTime<chrono::Utc> = time.into();
+    d.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
@@ -0,0 +1,246 @@
+use std::{error::Error, time::Duration};
+
+use super::credentials::mfa::MfaCodeRequired;
+use crate::error::{
+    self,
+    aws_error::{AwsError, AwsErrorKind},
+};
+
+// 1プロファイルあたりの確認のタイムアウト
+const CHECK_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// プロファイルの状態
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    // 認証情報が有効でバケット一覧を取得できる
+    Valid,
+    // 一時認証情報やSSOのセッションが失効している、保管庫がロック中(再認証が必要)
+    Expired,
+    // アクセスキーが無効、または認証情報を取得できない
+    InvalidKey,
+    // 認証情報は有効だがバケット一覧の取得が許可されていない
+    AccessDenied,
+    // エンドポイントに接続できない
+    Unreachable,
+    // 上記以外のエラー(サービスのエラーなど)
+    Error,
+}
+
+/// プロファイルごとの確認結果
+#[derive(serde::Serialize)]
+pub struct ProfileHealth {
+    pub profile: String,
+    pub status: HealthStatus,
+    // GetCallerIdentityで取得したアカウントID、ARN
+    pub account: Option<String>,
+    pub arn: Option<String>,
+    // 失敗した場合の詳細
+    pub message: Option<String>,
+}
+
+impl ProfileHealth {
+    fn failed(profile: &str, status: HealthStatus, message: impl ToString) -> Self {
+        ProfileHealth {
+            profile: profile.to_string(),
+            status,
+            account: None,
+            arn: None,
+            message: Some(message.to_string()),
+        }
+    }
+}
+
+/// すべてのプロファイルの状態を並行して確認する
+/// MFAコードの入力が必要なプロファイルは入力を要求せず`Expired`とする
+/// AWSのconfig, credentialファイルを読み込めない場合はエラー
+pub async fn check_all() -> Result<Vec<ProfileHealth>, AwsError> {
+    let tasks = super::list_profile_name()?
+        .into_iter()
+        .map(|name| tokio::spawn(check(name)))
+        .collect::<Vec<_>>();
+
+    let mut list = Vec::<ProfileHealth>::new();
+    for task in tasks {
+        match task.await {
+            Ok(v) => list.push(v),
+            Err(err) => println!("health check task error: {}", err),
+        }
+    }
+    Ok(list)
+}
+
+/// 指定のプロファイルの状態を確認する
+pub async fn check(name: String) -> ProfileHealth {
+    let probe = super::credentials::mfa::without_prompt(probe(&name));
+    match tokio::time::timeout(CHECK_TIMEOUT, probe).await {
+        Ok(v) => v,
+        Err(_) => ProfileHealth::failed(&name, HealthStatus::Unreachable, "timed out"),
+    }
+}
+
+// GetCallerIdentity, ListBucketsを順に実行して状態を判定する
+async fn probe(name: &str) -> ProfileHealth {
+    let region = match super::profile_region(Some(name), None) {
+        Ok(v) => v,
+        Err(err) => return ProfileHealth::failed(name, status_of(&err), error_message(&err)),
+    };
+    let config = super::profile_config(name, &region.name).await;
+    let conn = crate::config::find_connection(name);
+
+    // 匿名アクセスの場合は確認する認証情報が存在しないため、接続できるかのみ確認する
+    if let Some(c) = conn.as_ref().filter(|c| c.anonymous) {
+        let client = super::endpoint::s3_client(&config, c, None);
+        return probe_anonymous(name, &client, &c.buckets).await;
+    }
+
+    let mut health = ProfileHealth {
+        profile: name.to_string(),
+        status: HealthStatus::Valid,
+        account: None,
+        arn: None,
+        message: None,
+    };
+
+    // S3互換のエンドポイントはSTSに対応していない場合があるため、ListBucketsのみで確認する
+    if conn.as_ref().map_or(true, |c| c.endpoint_url.is_none()) {
+        let client = match &conn {
+            Some(c) => super::endpoint::sts_client(&config, c),
+            None => aws_sdk_sts::Client::new(&config),
+        };
+        match client.get_caller_identity().send().await {
+            Ok(res) => {
+                health.account = res.account().map(|v| v.to_string());
+                health.arn = res.arn().map(|v| v.to_string());
+            }
+            Err(err) => {
+                let err = AwsError::from_sdk(err);
+                return ProfileHealth::failed(name, status_of(&err), error_message(&err));
+            }
+        }
+    }
+
+    // バケット一覧の取得が許可されているか確認
+    let client = match &conn {
+        Some(c) => super::endpoint::s3_client(&config, c, None),
+        None => aws_sdk_s3::Client::new(&config),
+    };
+    if let Err(err) = client.list_buckets().send().await {
+        let err = AwsError::from_sdk(err);
+        health.status = status_of(&err);
+        health.message = Some(error_message(&err));
+    }
+    health
+}
+
+// 匿名アクセスの接続設定の状態を判定する
+// 登録済みのバケットがある場合はオブジェクトを1件取得し、ない場合はバケット一覧を取得する
+// バケット一覧の取得が拒否された場合も、エンドポイントには接続できているため有効とする
+async fn probe_anonymous(
+    name: &str,
+    client: &aws_sdk_s3::Client,
+    buckets: &[String],
+) -> ProfileHealth {
+    let mut health = ProfileHealth {
+        profile: name.to_string(),
+        status: HealthStatus::Valid,
+        account: None,
+        arn: None,
+        message: Some("anonymous access".to_string()),
+    };
+
+    if buckets.is_empty() {
+        if let Err(err) = client.list_buckets().send().await {
+            let err = AwsError::from_sdk(err);
+            if !matches!(err.kind(), AwsErrorKind::AccessDenied) {
+                health.status = status_of(&err);
+                health.message = Some(error_message(&err));
+            }
+        }
+        return health;
+    }
+
+    for bucket in buckets {
+        let res = client
+            .list_objects_v2()
+            .bucket(bucket)
+            .max_keys(1)
+            .send()
+            .await;
+        if let Err(err) = res {
+            let err = AwsError::from_sdk(err).bucket(bucket);
+            health.status = status_of(&err);
+            health.message = Some(error_message(&err));
+            break;
+        }
+    }
+    health
+}
+
+// エラーの種類から状態を判定する
+// 認証、認可に関係しないサービスのエラーは`Error`とする
+fn status_of(err: &AwsError) -> HealthStatus {
+    match err.kind() {
+        // 再認証が必要な場合
+        AwsErrorKind::ExpiredToken | AwsErrorKind::SsoTokenExpired | AwsErrorKind::VaultLocked => {
+            HealthStatus::Expired
+        }
+        AwsErrorKind::Credentials if error::find_source::<MfaCodeRequired>(err).is_some() => {
+            HealthStatus::Expired
+        }
+        AwsErrorKind::InvalidAccessKeyId
+        | AwsErrorKind::SignatureDoesNotMatch
+        | AwsErrorKind::Credentials
+        | AwsErrorKind::InvalidArgument
+        | AwsErrorKind::ProfileNotFound
+        | AwsErrorKind::ProfileFile => HealthStatus::InvalidKey,
+        AwsErrorKind::AccessDenied => HealthStatus::AccessDenied,
+        AwsErrorKind::Timeout | AwsErrorKind::Dns | AwsErrorKind::Tls | AwsErrorKind::Network => {
+            HealthStatus::Unreachable
+        }
+        _ => HealthStatus::Error,
+    }
+}
+
+// エラーの発生元を含めたメッセージ
+// 直接の発生元は`AwsError`のメッセージに含まれる
+fn error_message(err: &AwsError) -> String {
+    let mut message = err.to_string();
+    let mut current = err.source().and_then(|e| e.source());
+    while let Some(e) = current {
+        message = format!("{}: {}", message, e);
+        current = e.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(kind: AwsErrorKind) -> HealthStatus {
+        status_of(&AwsError::new(kind))
+    }
+
+    #[test]
+    fn only_authorization_errors_are_access_denied() {
+        assert_eq!(
+            status(AwsErrorKind::AccessDenied),
+            HealthStatus::AccessDenied
+        );
+        assert_eq!(status(AwsErrorKind::NoSuchBucket), HealthStatus::Error);
+        assert_eq!(status(AwsErrorKind::SlowDown), HealthStatus::Error);
+        assert_eq!(status(AwsErrorKind::Service), HealthStatus::Error);
+        assert_eq!(status(AwsErrorKind::Unknown), HealthStatus::Error);
+    }
+
+    #[test]
+    fn credential_and_network_errors_keep_their_status() {
+        assert_eq!(status(AwsErrorKind::ExpiredToken), HealthStatus::Expired);
+        assert_eq!(
+            status(AwsErrorKind::InvalidAccessKeyId),
+            HealthStatus::InvalidKey
+        );
+        assert_eq!(status(AwsErrorKind::Timeout), HealthStatus::Unreachable);
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use once_cell::sync::Lazy;
+use tauri::Manager;
+use tokio::task::JoinHandle;
+
+use super::{credentials::mfa, session::Session};
+use crate::error::aws_error::AwsError;
+
+/// 認証情報の有効期限が近づいたことを通知するイベント名
+pub const CREDENTIALS_EXPIRING_EVENT: &str = "credentials-expiring";
+
+// 有効期限のこの時間前に通知する
+const WARN_BEFORE_EXPIRY: Duration = Duration::from_secs(10 * 60);
+
+// 有効期限の通知を待機しているタスク(セッションID → タスク)
+static EXPIRY_WATCH: Lazy<Mutex<HashMap<String, JoinHandle<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 選択中のプロファイルで操作しているアカウント、ロールの情報
+#[derive(serde::Serialize)]
+pub struct CallerIdentity {
+    pub profile: Option<String>,
+    pub account: Option<String>,
+    pub arn: Option<String>,
+    pub user_id: Option<String>,
+    // 一時認証情報の有効期限(固定の認証情報の場合は`None`)
+    pub expiration: Option<String>,
+    // 匿名アクセスの場合はtrue
+    pub anonymous: bool,
+}
+
+/// 有効期限が近づいたときのイベント内容
+#[derive(Clone, serde::Serialize)]
+pub struct CredentialsExpiring {
+    pub session_id: String,
+    pub profile: Option<String>,
+    pub expiration: String,
+    // 有効期限までの残り秒数
+    pub remaining_seconds: u64,
+}
+
+/// GetCallerIdentityで認証情報の利用者を取得する
+/// 一時認証情報の場合は有効期限の前にイベントで通知する
+pub async fn caller_identity(session: &Session) -> Result<CallerIdentity, AwsError> {
+    let profile = session.profile.clone();
+    let conn = &session.connection;
+
+    // 匿名アクセスの場合は認証情報が存在しない
+    if conn.as_ref().map_or(false, |c| c.anonymous) {
+        stop_watch(&session.id);
+        return Ok(CallerIdentity {
+            profile,
+            account: None,
+            arn: None,
+            user_id: None,
+            expiration: None,
+            anonymous: true,
+        });
+    }
+
+    // aws config
+    let config = &session.config;
+
+    // 有効期限を取得するため認証情報を解決する
+    let expiry = session.credentials().await?.and_then(|c| c.expiry());
+
+    // STS client
+    let client = match conn {
+        Some(c) => super::endpoint::sts_client(config, c),
+        None => aws_sdk_sts::Client::new(config),
+    };
+
+    let res = client
+        .get_caller_identity()
+        .send()
+        .await
+        .map_err(AwsError::from_sdk)?;
+
+    watch_expiry(session, expiry);
+
+    Ok(CallerIdentity {
+        profile,
+        account: res.account().map(|v| v.to_string()),
+        arn: res.arn().map(|v| v.to_string()),
+        user_id: res.user_id().map(|v| v.to_string()),
+        expiration: expiry.map(format_time),
+        anonymous: false,
+    })
+}
+
+/// セッションの有効期限の通知を停止する
+pub fn stop_watch(session_id: &str) {
+    if let Some(task) = EXPIRY_WATCH.lock().unwrap().remove(session_id) {
+        task.abort();
+    }
+}
+
+// 有効期限の通知を予約する
+// 既に予約されている通知は取り消す
+fn watch_expiry(session: &Session, expiry: Option<SystemTime>) {
+    stop_watch(&session.id);
+
+    let expiry = match expiry {
+        Some(v) => v,
+        None => return,
+    };
+    let app = match mfa::app_handle() {
+        Some(v) => v,
+        None => return,
+    };
+
+    let session_id = session.id.clone();
+    let profile = session.profile.clone();
+    let task = tokio::spawn(async move {
+        // 既に通知する時間を過ぎている場合はすぐに通知する
+        let notify_at = expiry
+            .checked_sub(WARN_BEFORE_EXPIRY)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        if let Ok(wait) = notify_at.duration_since(SystemTime::now()) {
+            tokio::time::sleep(wait).await;
+        }
+
+        let remaining = expiry.duration_since(SystemTime::now()).unwrap_or_default();
+        let payload = CredentialsExpiring {
+            session_id,
+            profile,
+            expiration: format_time(expiry),
+            remaining_seconds: remaining.as_secs(),
+        };
+        if let Err(err) = app.emit_all(CREDENTIALS_EXPIRING_EVENT, payload) {
+            println!("emit {} error: {}", CREDENTIALS_EXPIRING_EVENT, err);
+        }
+    });
+    EXPIRY_WATCH
+        .lock()
+        .unwrap()
+        .insert(session.id.clone(), task);
+}
+
+// 画面表示用の日時に変換する
+fn format_time(time: SystemTime) -> String {
+    let d: chrono::DateTime<chrono::Local> = time.into();
+    d.format("%Y/%m/%d %H:%M:%S").to_string()
+}
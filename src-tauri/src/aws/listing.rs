@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use tauri::{AppHandle, Manager};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_stream::StreamExt;
+
+use super::{
+    s3::{self, ObjectPageFormatter, S3Object, S3ObjectTotals},
+    session::Session,
+    task,
+};
+use crate::error::aws_error::{AwsError, AwsErrorKind};
+
+/// オブジェクト一覧のページを通知するイベント名
+pub const OBJECTS_PAGE_EVENT: &str = "objects-page";
+
+// 次のページが要求されないまま待機する時間
+// 画面が一覧を破棄した場合に取得中の状態が残らないよう、経過後は終了する
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// 次のページの要求を受け付ける一覧取得(一覧取得ID → 要求の送信側)
+static CURSORS: Lazy<Mutex<HashMap<String, mpsc::Sender<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 画面に通知する1ページ分の一覧
+#[derive(Clone, serde::Serialize)]
+pub struct ObjectsPage {
+    pub listing_id: String,
+    pub bucket: String,
+    pub prefix: Option<String>,
+    // 1始まりのページ番号
+    pub page: u32,
+    pub objects: Vec<S3Object>,
+    // 1ページ目からの累計
+    pub totals: S3ObjectTotals,
+    // 最後のページの場合はtrue(失敗した場合を含む)
+    pub done: bool,
+    // 失敗した場合のエラー(`AwsError`をシリアライズしたもの)
+    pub error: Option<serde_json::Value>,
+}
+
+/// オブジェクト一覧の取得を開始し、1ページ目(最大1000件)をイベントで通知する
+/// 2ページ目以降は`next_page`で要求されるごとに1ページずつ取得して通知する
+/// 戻り値の一覧取得IDで通知の判別、次のページの要求、取り消しを行う
+pub fn start(
+    app: AppHandle,
+    session: Arc<Session>,
+    bucket_name: String,
+    prefix: Option<String>,
+    recursive: bool,
+) -> String {
+    // 要求は処理されるまで1件のみ保持する
+    let (tx, rx) = mpsc::channel(1);
+    task::start("listing", move |id| {
+        CURSORS.lock().unwrap().insert(id.clone(), tx);
+        async move {
+            run(app, &session, &id, bucket_name, prefix, recursive, rx).await;
+            CURSORS.lock().unwrap().remove(&id);
+        }
+    })
+}
+
+/// 次のページの取得を要求する
+/// 前の要求を処理中の場合は重ねて要求しない
+pub fn next_page(listing_id: &str) -> Result<(), AwsError> {
+    let not_running = || {
+        AwsError::new(AwsErrorKind::InvalidArgument)
+            .message(format!("listing `{}` is not running", listing_id))
+    };
+
+    let cursors = CURSORS.lock().unwrap();
+    let tx = cursors.get(listing_id).ok_or_else(not_running)?;
+    match tx.try_send(()) {
+        Ok(_) | Err(TrySendError::Full(_)) => Ok(()),
+        Err(TrySendError::Closed(_)) => Err(not_running()),
+    }
+}
+
+/// 実行中の一覧取得を取り消す
+/// 取り消した後はイベントを通知しない
+pub fn cancel(listing_id: &str) {
+    task::cancel(listing_id);
+    CURSORS.lock().unwrap().remove(listing_id);
+}
+
+// 一覧を取得して通知する
+async fn run(
+    app: AppHandle,
+    session: &Session,
+    listing_id: &str,
+    bucket_name: String,
+    prefix: Option<String>,
+    recursive: bool,
+    mut next: mpsc::Receiver<()>,
+) {
+    // バケットに設定された区切り文字
+    let delimiter = session.delimiter(&bucket_name);
+
+    let mut stream =
+        s3::object_pages(session, &bucket_name, prefix.clone(), recursive, delimiter).await;
+    let mut formatter = ObjectPageFormatter::new(prefix.clone(), recursive, delimiter);
+    let mut totals = S3ObjectTotals::default();
+    let mut page = 0;
+
+    // 1ページ取得するごとに通知し、画面から次のページが要求されるまで待機する
+    // ページネータは次のページを先行して取得するため、待機中も1ページ分のリクエストは送信される
+    // (それ以降のページはストリームを読み進めるまで要求されない)
+    while let Some(res) = stream.next().await {
+        page += 1;
+        let (objects, done, error) = match res {
+            Ok(data) => (formatter.format(&data), !data.is_truncated(), None),
+            Err(err) => {
+                let err = s3::sdk_error(err, &bucket_name, formatter.prefix());
+                (Vec::new(), true, serde_json::to_value(&err).ok())
+            }
+        };
+        totals.add(&objects);
+
+        let payload = ObjectsPage {
+            listing_id: listing_id.to_string(),
+            bucket: bucket_name.clone(),
+            prefix: prefix.clone(),
+            page,
+            objects,
+            totals,
+            done,
+            error,
+        };
+        if let Err(err) = app.emit_all(OBJECTS_PAGE_EVENT, payload) {
+            println!("emit {} error: {}", OBJECTS_PAGE_EVENT, err);
+            return;
+        }
+        if done {
+            return;
+        }
+
+        match tokio::time::timeout(IDLE_TIMEOUT, next.recv()).await {
+            Ok(Some(_)) => {}
+            // 取り消された、または要求がないまま時間が経過した場合
+            Ok(None) | Err(_) => return,
+        }
+    }
+}
@@ -1,48 +1,118 @@
 use aws_types::SdkConfig;
 use std::time::SystemTime;
 
+use crate::{
+    config::connection::Connection,
+    error::aws_error::{AwsError, AwsErrorKind},
+};
+
+pub mod credentials;
+pub mod editor;
+pub mod endpoint;
+pub mod export;
+pub mod health;
+pub mod identity;
+pub mod listing;
+pub mod parser;
+pub mod path;
 pub mod profile;
+pub mod region;
 pub mod s3;
+pub mod search;
+pub mod session;
+pub mod task;
 
-/// Get AWS Config
-pub async fn aws_config() -> SdkConfig {
-    // リージョン名
-    let region_name = "ap-northeast-1";
-
-    // 選択中のプロファイルを取得するためconfigファイルを読み込む
-    let user_config = super::config::read_config();
+/// プロファイルを使用しない場合のAWS Config
+/// 環境情報のデフォルト値から取得する
+pub async fn default_config(region_name: &str) -> SdkConfig {
+    aws_config::from_env()
+        .region(aws_sdk_sts::Region::new(region_name.to_string()))
+        .load()
+        .await
+}
 
-    // 選択中のプロファイルが存在する場合はリストから取得
-    if let Some(selected) = user_config.profile {
-        // プロファイル名をリストから取得
-        let p_map = profile::read_profiles();
+/// プロファイル(または接続設定)で使用するリージョンを解決する
+/// `overridden`はセッションごとのリージョンの上書き設定
+/// プロファイルが存在しない場合や、AWSのconfig, credentialファイルを読み込めない場合はエラー
+pub fn profile_region(
+    name: Option<&str>,
+    overridden: Option<&str>,
+) -> Result<region::ResolvedRegion, AwsError> {
+    let name = match name {
+        Some(v) => v,
+        None => return Ok(region::resolve(overridden, None)),
+    };
 
-        // プロファイルを取得
-        if let Some(p) = p_map.get(&selected) {
-            // awsのクレデンシャルを生成
-            let cred = aws_types::Credentials::new(
-                p.access_key_id.clone().unwrap(),
-                p.secret_access_key.clone().unwrap(),
-                p.session_token.clone(),
-                None,
-                "Statics",
-            );
+    // S3互換のエンドポイントや匿名アクセスの接続設定の場合
+    if let Some(conn) = super::config::find_connection(name) {
+        return Ok(region::resolve(overridden, conn.region.as_deref()));
+    }
 
-            return aws_config::from_env()
-                .region(aws_sdk_sts::Region::new(region_name))
-                .credentials_provider(cred)
-                .load()
-                .await;
+    // 疑似プロファイルの場合は環境情報のデフォルト値を使用
+    let p_map = profile::read_profiles()?;
+    match p_map.get(name) {
+        Some(p) => Ok(region::resolve(overridden, p.region.as_deref())),
+        None if credentials::sources::Source::from_profile_name(name).is_some() => {
+            Ok(region::resolve(overridden, None))
         }
+        None => Err(AwsError::new(AwsErrorKind::ProfileNotFound)
+            .message(format!("profile `{}` does not exist", name))),
     }
+}
+
+/// 指定のプロファイル(または接続設定)のAWS Configを取得
+/// リージョンは`profile_region`で解決したものを指定する
+pub async fn profile_config(name: &str, region_name: &str) -> SdkConfig {
+    // S3互換のエンドポイントや匿名アクセスの接続設定の場合
+    if let Some(conn) = super::config::find_connection(name) {
+        return connection_config(&conn, region_name).await;
+    }
+
+    // プロファイルの設定から認証情報を解決するプロバイダを生成
+    let cred = credentials::profile_provider(name);
 
-    // 存在しない場合は環境情報のデフォルト値から取得
     aws_config::from_env()
-        .region(aws_sdk_sts::Region::new(region_name))
+        .region(aws_sdk_sts::Region::new(region_name.to_string()))
+        .credentials_provider(cred)
         .load()
         .await
 }
 
+// 接続設定を使用する場合の設定
+async fn connection_config(conn: &Connection, region_name: &str) -> SdkConfig {
+    let loader = aws_config::from_env().region(aws_sdk_sts::Region::new(region_name.to_string()));
+
+    // 匿名アクセスの場合は署名を除去するためダミーの認証情報を使用
+    if conn.anonymous {
+        return loader
+            .credentials_provider(endpoint::anonymous_credentials())
+            .load()
+            .await;
+    }
+
+    // プロファイルの指定がある場合はそのプロファイルの認証情報を使用
+    if let Some(p) = &conn.profile {
+        return loader
+            .credentials_provider(credentials::profile_provider(p.clone()))
+            .load()
+            .await;
+    }
+
+    match (&conn.access_key_id, &conn.secret_access_key) {
+        (Some(id), Some(secret)) => {
+            let cred = aws_types::Credentials::new(
+                id,
+                secret,
+                conn.session_token.clone(),
+                None,
+                "Connection",
+            );
+            loader.credentials_provider(cred).load().await
+        }
+        _ => loader.load().await,
+    }
+}
+
 /// Parse date string from aws datetime
 pub fn parse_datetime(datetime: &aws_smithy_types::DateTime) -> String {
     match SystemTime::try_from(*datetime) {
@@ -55,14 +125,57 @@ pub fn parse_datetime(datetime: &aws_smithy_types::DateTime) -> String {
 }
 
 /// プロファイル名のみを取得する
-pub fn list_profile_name() -> Vec<String> {
-    let p = profile::read_profiles();
+/// S3互換のエンドポイントへの接続設定や環境から取得する認証情報も選択できるように含める
+pub fn list_profile_name() -> Result<Vec<String>, AwsError> {
+    let p = profile::read_profiles()?;
     let mut list = p.iter().map(|x| x.0.clone()).collect::<Vec<String>>();
+    for conn in super::config::list_connections() {
+        if !list.contains(&conn.name) {
+            list.push(conn.name);
+        }
+    }
     list.sort();
-    list
+
+    // 環境から取得できる認証情報は疑似プロファイルとして末尾に追加
+    list.extend(credentials::sources::available_profile_names());
+    Ok(list)
+}
+
+/// S3互換のエンドポイントへの接続設定を保存する
+pub fn save_connection(conn: Connection) -> Result<(), String> {
+    if conn.name.trim().is_empty() {
+        return Err("connection name must not be empty".to_string());
+    }
+    if conn
+        .name
+        .starts_with(credentials::sources::PSEUDO_PROFILE_PREFIX)
+    {
+        return Err(format!(
+            "connection name must not start with `{}`",
+            credentials::sources::PSEUDO_PROFILE_PREFIX
+        ));
+    }
+    // AWSのプロファイルと同じ名称は使用できない
+    let profiles = profile::read_profiles().map_err(|err| err.to_string())?;
+    if profiles.contains_key(&conn.name) {
+        return Err(format!("profile `{}` already exists", conn.name));
+    }
+    if let Some(url) = &conn.endpoint_url {
+        if let Err(err) = url.trim().parse::<http::Uri>() {
+            return Err(format!("invalid endpoint url: {}", err));
+        }
+    }
+    super::config::save_connection(conn);
+    Ok(())
+}
+
+/// プロファイルの設定を取得する
+pub fn get_profile(name: &str) -> Result<Option<profile::AWSProfile>, AwsError> {
+    Ok(profile::read_profiles()?.remove(name))
 }
 
 /// 使用するプロファイルを選択する
+/// 新しく開始するセッションのデフォルトとなる
 pub fn set_profile(p: String) {
     let mut user_config = super::config::read_config();
 
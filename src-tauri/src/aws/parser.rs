@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+
+/// `sso-session`セクションのプレフィックス
+pub const SSO_SESSION_PREFIX: &str = "sso-session";
+
+// プロファイルセクションのプレフィックス(configファイルのみ)
+const PROFILE_PREFIX: &str = "profile";
+
+/// 解析対象のファイルの種類
+/// configファイルとcredentialファイルではセクション名の扱いが異なる
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    Config,
+    Credentials,
+}
+
+/// 解析エラー
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct ParseError {
+    // 1始まりの行番号
+    pub line: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        ParseError {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// セクション内のプロパティ
+#[derive(Clone, Debug, PartialEq)]
+pub struct Property {
+    pub key: String,
+    pub value: String,
+    // `s3 =`のようにネストされたプロパティ
+    pub sub_properties: Vec<(String, String)>,
+    // プロパティが記述されている行の範囲(1始まり、終了行を含む)
+    pub line: usize,
+    pub end_line: usize,
+}
+
+/// `[...]`で囲まれたセクション
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    // 括弧内の文字列(前後の空白は除去済み)
+    pub header: String,
+    pub properties: Vec<Property>,
+    // セクションが記述されている行の範囲(1始まり、終了行を含む)
+    pub line: usize,
+    pub end_line: usize,
+}
+
+/// AWSのconfig, credentialファイルを解析する
+pub fn parse(text: &str) -> Result<Vec<Section>, ParseError> {
+    let mut sections = Vec::<Section>::new();
+
+    for (index, raw) in text.lines().enumerate() {
+        let line_no = index + 1;
+
+        // 空行、コメント行は無視
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+
+        // セクション定義の行
+        if trimmed.starts_with('[') {
+            let header = parse_section_header(trimmed, line_no)?;
+            sections.push(Section {
+                header,
+                properties: Vec::new(),
+                line: line_no,
+                end_line: line_no,
+            });
+            continue;
+        }
+
+        // セクションの定義より前にプロパティが記述されている場合はエラー
+        let section = sections.last_mut().ok_or_else(|| {
+            ParseError::new(line_no, "property is defined before any section header")
+        })?;
+
+        // 先頭が空白の場合は直前のプロパティの続き
+        if raw.starts_with(|c: char| c.is_whitespace()) {
+            let property = section.properties.last_mut().ok_or_else(|| {
+                ParseError::new(line_no, "continuation line without a preceding property")
+            })?;
+            let line = strip_comment(trimmed, true);
+
+            if property.value.is_empty() || !property.sub_properties.is_empty() {
+                // 値が空のプロパティに続く場合はネストされたプロパティとして扱う
+                let (key, value) = split_property(line, line_no)?;
+                property.sub_properties.push((key, value));
+            } else {
+                // それ以外は値を改行で連結する
+                property.value = format!("{}\n{}", property.value, line);
+            }
+            property.end_line = line_no;
+            section.end_line = line_no;
+            continue;
+        }
+
+        let (key, value) = split_property(strip_comment(trimmed, true), line_no)?;
+        section.properties.push(Property {
+            key,
+            value,
+            sub_properties: Vec::new(),
+            line: line_no,
+            end_line: line_no,
+        });
+        section.end_line = line_no;
+    }
+
+    Ok(sections)
+}
+
+/// 解析したセクションをプロファイル名(またはsso-session名)ごとのMapに変換する
+///
+/// - configファイルは`[default]`と`[profile xxx]`をプロファイルとし、
+///   `[profile default]`は`[default]`より優先する
+/// - credentialファイルはセクション名をそのままプロファイル名とする
+/// - `[sso-session xxx]`はキー名を`sso-session xxx`のまま返却する
+/// - ネストされたプロパティは`親.子`のキーで格納する
+pub fn normalize(sections: &[Section], kind: FileKind) -> HashMap<String, HashMap<String, String>> {
+    let mut result = HashMap::<String, HashMap<String, String>>::new();
+
+    // `[profile default]`が`[default]`より優先されるように後から適用する
+    let mut ordered = sections.iter().collect::<Vec<&Section>>();
+    ordered.sort_by_key(|s| s.header.starts_with(PROFILE_PREFIX));
+
+    for section in ordered {
+        let name = match profile_name(&section.header, kind) {
+            Some(n) => n,
+            None => {
+                println!("ignored section: [{}]", section.header);
+                continue;
+            }
+        };
+
+        // 同一セクションが複数存在する場合は後のものを優先してマージ
+        let map = result.entry(name).or_default();
+        for p in &section.properties {
+            map.insert(p.key.clone(), p.value.clone());
+            for (k, v) in &p.sub_properties {
+                map.insert(format!("{}.{}", p.key, k), v.clone());
+            }
+        }
+    }
+
+    result
+}
+
+/// セクション名からプロファイル名を取得する
+/// プロファイルとして扱わないセクションの場合は`None`
+pub fn profile_name(header: &str, kind: FileKind) -> Option<String> {
+    match kind {
+        FileKind::Config => {
+            if header == "default" {
+                return Some(header.to_string());
+            }
+            if let Some(name) = strip_keyword(header, SSO_SESSION_PREFIX) {
+                return Some(format!("{} {}", SSO_SESSION_PREFIX, name));
+            }
+            strip_keyword(header, PROFILE_PREFIX)
+        }
+        FileKind::Credentials => {
+            // credentialファイルでは`profile`のプレフィックスは使用できない
+            if strip_keyword(header, PROFILE_PREFIX).is_some() {
+                return None;
+            }
+            Some(header.to_string())
+        }
+    }
+}
+
+// `キーワード 名称`形式のセクション名から名称を取得する
+fn strip_keyword(header: &str, keyword: &str) -> Option<String> {
+    let rest = header.strip_prefix(keyword)?;
+    // キーワードの後には空白が必要
+    if !rest.starts_with(|c: char| c.is_whitespace()) {
+        return None;
+    }
+    let name = rest.trim();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+// `[...]`の行からセクション名を取得する
+fn parse_section_header(line: &str, line_no: usize) -> Result<String, ParseError> {
+    // セクション定義の行では空白なしのコメントも許容する
+    let line = strip_comment(line, false);
+    if !line.ends_with(']') {
+        return Err(ParseError::new(
+            line_no,
+            "section header is not closed with `]`",
+        ));
+    }
+
+    let header = line[1..(line.len() - 1)].trim();
+    if header.is_empty() {
+        return Err(ParseError::new(line_no, "section name is empty"));
+    }
+    Ok(header.to_string())
+}
+
+// `key = value`の行を分割する
+// 値に`=`が含まれる場合(Base64など)を考慮し、最初の`=`でのみ分割する
+fn split_property(line: &str, line_no: usize) -> Result<(String, String), ParseError> {
+    let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| ParseError::new(line_no, "expected `key = value`"))?;
+
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(ParseError::new(line_no, "property name is empty"));
+    }
+    Ok((key.to_string(), value.trim().to_string()))
+}
+
+// 行末のコメントを除去する
+// プロパティの行では直前に空白がある`#`, `;`のみコメントとして扱う
+fn strip_comment(line: &str, needs_whitespace: bool) -> &str {
+    let mut prev_is_whitespace = !needs_whitespace;
+    for (i, c) in line.char_indices() {
+        if (c == '#' || c == ';') && (prev_is_whitespace || !needs_whitespace) {
+            return line[..i].trim_end();
+        }
+        prev_is_whitespace = c.is_whitespace();
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property<'a>(section: &'a Section, key: &str) -> &'a Property {
+        section
+            .properties
+            .iter()
+            .find(|p| p.key == key)
+            .unwrap_or_else(|| panic!("property `{}` does not exist", key))
+    }
+
+    #[test]
+    fn parse_skips_comment_lines() {
+        let text = "# comment\n; comment\n[default]\n  # indented comment\nregion = us-east-1 # trailing\noutput = json ; trailing\n";
+        let sections = parse(text).unwrap();
+
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].header, "default");
+        assert_eq!(sections[0].line, 3);
+        assert_eq!(sections[0].properties.len(), 2);
+        assert_eq!(property(&sections[0], "region").value, "us-east-1");
+        assert_eq!(property(&sections[0], "output").value, "json");
+    }
+
+    #[test]
+    fn parse_keeps_comment_chars_inside_values() {
+        let text = "[default]\naws_secret_access_key = abc#def;ghi==\nrole_arn = arn:aws:iam::123:role/a;b\n";
+        let sections = parse(text).unwrap();
+
+        assert_eq!(
+            property(&sections[0], "aws_secret_access_key").value,
+            "abc#def;ghi=="
+        );
+        assert_eq!(
+            property(&sections[0], "role_arn").value,
+            "arn:aws:iam::123:role/a;b"
+        );
+    }
+
+    #[test]
+    fn parse_allows_comment_without_whitespace_after_header() {
+        let sections = parse("[profile dev]#comment\nregion = eu-west-1\n").unwrap();
+        assert_eq!(sections[0].header, "profile dev");
+    }
+
+    #[test]
+    fn parse_nested_sub_properties() {
+        let text = "[profile dev]\ns3 =\n  max_concurrent_requests = 20\n  addressing_style = path # comment\nregion = us-west-2\n";
+        let sections = parse(text).unwrap();
+
+        let s3 = property(&sections[0], "s3");
+        assert_eq!(s3.value, "");
+        assert_eq!(
+            s3.sub_properties,
+            vec![
+                ("max_concurrent_requests".to_string(), "20".to_string()),
+                ("addressing_style".to_string(), "path".to_string()),
+            ]
+        );
+        assert_eq!((s3.line, s3.end_line), (2, 4));
+        assert_eq!(property(&sections[0], "region").value, "us-west-2");
+        assert_eq!(sections[0].end_line, 5);
+
+        let map = normalize(&sections, FileKind::Config);
+        let dev = &map["dev"];
+        assert_eq!(dev["s3.max_concurrent_requests"], "20");
+        assert_eq!(dev["s3.addressing_style"], "path");
+        assert_eq!(dev["region"], "us-west-2");
+    }
+
+    #[test]
+    fn parse_continuation_line_appends_value() {
+        let sections = parse("[default]\nca_bundle = first\n  second\n").unwrap();
+        let p = property(&sections[0], "ca_bundle");
+        assert_eq!(p.value, "first\nsecond");
+        assert!(p.sub_properties.is_empty());
+    }
+
+    #[test]
+    fn parse_property_before_section_is_error() {
+        let err = parse("# comment\n\nregion = us-east-1\n[default]\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.to_string(), format!("line 3: {}", err.message));
+    }
+
+    #[test]
+    fn parse_reports_error_line_numbers() {
+        assert_eq!(parse("[default\n").unwrap_err().line, 1);
+        assert_eq!(parse("[default]\nregion\n").unwrap_err().line, 2);
+        assert_eq!(parse("[default]\n = value\n").unwrap_err().line, 2);
+        assert_eq!(parse("[default]\n\n[ ]\n").unwrap_err().line, 3);
+        assert_eq!(parse("[default]\n  orphan = 1\n").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn normalize_prefers_profile_default_in_config() {
+        let text =
+            "[profile default]\nregion = eu-west-1\n[default]\nregion = us-east-1\noutput = json\n";
+        let map = normalize(&parse(text).unwrap(), FileKind::Config);
+
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["default"]["region"], "eu-west-1");
+        // `[default]`のみに存在する値は残る
+        assert_eq!(map["default"]["output"], "json");
+    }
+
+    #[test]
+    fn normalize_section_names_by_file_kind() {
+        let text =
+            "[default]\na = 1\n[profile dev]\na = 2\n[sso-session corp]\na = 3\n[other]\na = 4\n";
+        let sections = parse(text).unwrap();
+
+        let config = normalize(&sections, FileKind::Config);
+        let mut names = config.keys().cloned().collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(names, vec!["default", "dev", "sso-session corp"]);
+
+        // credentialファイルでは`profile`のプレフィックスは使用できない
+        let credentials = normalize(&sections, FileKind::Credentials);
+        let mut names = credentials.keys().cloned().collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(names, vec!["default", "other", "sso-session corp"]);
+    }
+
+    #[test]
+    fn profile_name_requires_whitespace_after_keyword() {
+        assert_eq!(profile_name("profiledev", FileKind::Config), None);
+        assert_eq!(profile_name("profile", FileKind::Config), None);
+        assert_eq!(
+            profile_name("profile   dev ", FileKind::Config),
+            Some("dev".to_string())
+        );
+    }
+}
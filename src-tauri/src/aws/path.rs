@@ -0,0 +1,238 @@
+use std::path::PathBuf;
+
+/// 既定の区切り文字
+pub const DELIMITER: char = '/';
+
+/// S3のキー、またはプレフィックス
+///
+/// S3のキーは区切り文字を特別扱いしないため、先頭の区切り文字や`//`(空のセグメント)を含むキーも
+/// 正規化せずそのまま保持する。末尾が区切り文字のものをフォルダとして扱い、空文字はバケットの直下を表す
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct S3Path {
+    key: String,
+    // 階層の区切り文字(バケットごとに設定可能)
+    delimiter: char,
+}
+
+impl S3Path {
+    pub fn new(key: impl Into<String>, delimiter: char) -> Self {
+        S3Path {
+            key: key.into(),
+            delimiter,
+        }
+    }
+
+    /// バケットの直下
+    pub fn root(delimiter: char) -> Self {
+        S3Path::new(String::new(), delimiter)
+    }
+
+    /// 画面から指定されたプレフィックスをフォルダとして解釈する
+    /// 未指定、空文字の場合はバケットの直下
+    pub fn folder(prefix: Option<&str>, delimiter: char) -> Self {
+        match prefix {
+            Some(p) => S3Path::new(p, delimiter).to_folder(),
+            None => S3Path::root(delimiter),
+        }
+    }
+
+    /// 同じ区切り文字を使用するキーを生成する
+    pub fn sibling(&self, key: impl Into<String>) -> S3Path {
+        S3Path::new(key, self.delimiter)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.key
+    }
+
+    pub fn delimiter(&self) -> char {
+        self.delimiter
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.key.is_empty()
+    }
+
+    /// フォルダ(末尾が区切り文字)かどうか
+    pub fn is_folder(&self) -> bool {
+        self.key.ends_with(self.delimiter)
+    }
+
+    /// 末尾に区切り文字を付与してフォルダとする
+    /// 既にフォルダの場合やバケットの直下の場合はそのまま
+    pub fn to_folder(&self) -> S3Path {
+        if self.is_root() || self.is_folder() {
+            self.clone()
+        } else {
+            self.sibling(format!("{}{}", self.key, self.delimiter))
+        }
+    }
+
+    /// フォルダの直下に名前を連結する
+    /// `name`は加工しないため、先頭の区切り文字は空のセグメントとして扱われる
+    pub fn join(&self, name: &str) -> S3Path {
+        self.sibling(format!("{}{}", self.to_folder().key, name))
+    }
+
+    /// 指定のフォルダ配下の場合は、フォルダからの相対パスを取得する
+    /// 先頭一致のみで判定するため、キーの途中に同じ文字列があっても影響しない
+    pub fn strip_prefix(&self, folder: &S3Path) -> Option<&str> {
+        self.key.strip_prefix(folder.to_folder().as_str())
+    }
+
+    /// 最後のセグメント(ファイル名、またはフォルダ名)
+    /// `a/b`、`a/b/`のどちらも`b`となる
+    pub fn name(&self) -> &str {
+        let key = self.key.strip_suffix(self.delimiter).unwrap_or(&self.key);
+        match key.rfind(self.delimiter) {
+            Some(i) => &key[(i + self.delimiter.len_utf8())..],
+            None => key,
+        }
+    }
+
+    /// 相対パスの最初のセグメントを取得する
+    /// 続きがある場合(フォルダ配下の場合)は`true`を合わせて返却する
+    pub fn first_segment<'a>(&self, relative: &'a str) -> (&'a str, bool) {
+        match relative.split_once(self.delimiter) {
+            Some((first, _)) => (first, true),
+            None => (relative, false),
+        }
+    }
+
+    /// 相対パスをローカルのパスに変換する
+    /// 区切り文字に加えてローカルのパスの区切り文字でも分割し、
+    /// 空のセグメント、`.`、`..`はダウンロード先の外に書き込まないよう除外する
+    pub fn to_local_path(&self, relative: &str) -> PathBuf {
+        relative
+            .split([self.delimiter, '/', '\\'])
+            .filter(|s| !s.is_empty() && *s != "." && *s != "..")
+            .collect()
+    }
+}
+
+impl std::fmt::Display for S3Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(key: &str) -> S3Path {
+        S3Path::new(key, DELIMITER)
+    }
+
+    #[test]
+    fn strip_prefix_matches_only_the_leading_folder() {
+        let key = path("a/b/a/b/x");
+        assert_eq!(key.strip_prefix(&path("a/b")), Some("a/b/x"));
+        assert_eq!(key.strip_prefix(&path("a/b/")), Some("a/b/x"));
+        assert_eq!(key.strip_prefix(&path("a/b/a/b")), Some("x"));
+        assert_eq!(key.strip_prefix(&path("b")), None);
+        // 同じ文字列で始まる別のフォルダは含めない
+        assert_eq!(path("a/bc/x").strip_prefix(&path("a/b")), None);
+    }
+
+    #[test]
+    fn to_folder_and_join_do_not_double_the_delimiter() {
+        assert_eq!(path("a/b").to_folder().as_str(), "a/b/");
+        assert_eq!(path("a/b/").to_folder().as_str(), "a/b/");
+        assert_eq!(path("a/b").join("c.txt").as_str(), "a/b/c.txt");
+        assert_eq!(path("a/b/").join("c.txt").as_str(), "a/b/c.txt");
+        assert_eq!(
+            S3Path::folder(Some("a/b/"), DELIMITER)
+                .join("c.txt")
+                .as_str(),
+            "a/b/c.txt"
+        );
+    }
+
+    #[test]
+    fn keys_with_empty_segments_are_kept_as_is() {
+        let key = path("a//b");
+        assert_eq!(key.as_str(), "a//b");
+        assert_eq!(key.name(), "b");
+        assert_eq!(key.strip_prefix(&path("a/")), Some("/b"));
+        assert_eq!(path("a/").join("/b").as_str(), "a//b");
+
+        let key = path("/a/b");
+        assert!(!key.is_root());
+        assert_eq!(key.strip_prefix(&path("/")), Some("a/b"));
+        assert_eq!(key.strip_prefix(&path("a")), None);
+        assert_eq!(path("/").first_segment("a/b"), ("a", true));
+        assert_eq!(path("").first_segment("/a"), ("", true));
+    }
+
+    #[test]
+    fn empty_prefix_is_bucket_root() {
+        let root = S3Path::root(DELIMITER);
+        assert!(root.is_root());
+        assert!(!root.is_folder());
+        assert_eq!(root.to_folder().as_str(), "");
+        assert_eq!(root.join("a.txt").as_str(), "a.txt");
+        assert_eq!(S3Path::folder(None, DELIMITER), root);
+        assert_eq!(S3Path::folder(Some(""), DELIMITER), root);
+        assert_eq!(path("a/b").strip_prefix(&root), Some("a/b"));
+        assert_eq!(root.name(), "");
+    }
+
+    #[test]
+    fn unicode_segments() {
+        let key = path("写真/2024年/夏休み.jpg");
+        assert_eq!(key.name(), "夏休み.jpg");
+        assert_eq!(key.strip_prefix(&path("写真")), Some("2024年/夏休み.jpg"));
+        assert_eq!(path("写真").join("é.txt").as_str(), "写真/é.txt");
+        assert_eq!(
+            path("写真").first_segment("2024年/夏休み.jpg"),
+            ("2024年", true)
+        );
+
+        // 複数バイトの区切り文字
+        let key = S3Path::new("a→b→c", '→');
+        assert_eq!(key.name(), "c");
+        assert_eq!(key.to_folder().as_str(), "a→b→c→");
+        assert_eq!(key.strip_prefix(&key.sibling("a→b")), Some("c"));
+    }
+
+    #[test]
+    fn name_ignores_trailing_delimiter() {
+        assert_eq!(path("a/b").name(), "b");
+        assert_eq!(path("a/b/").name(), "b");
+        assert_eq!(path("b").name(), "b");
+        assert!(path("a/b/").is_folder());
+        assert!(!path("a/b").is_folder());
+    }
+
+    #[test]
+    fn custom_delimiter() {
+        let key = S3Path::new("a:b/c:d", ':');
+        assert_eq!(key.name(), "d");
+        assert_eq!(key.to_folder().as_str(), "a:b/c:d:");
+        assert_eq!(key.first_segment("b/c:d"), ("b/c", true));
+        assert_eq!(key.strip_prefix(&key.sibling("a")), Some("b/c:d"));
+    }
+
+    #[test]
+    fn to_local_path_drops_unsafe_segments() {
+        let root = S3Path::root(DELIMITER);
+        assert_eq!(
+            root.to_local_path("a/./b//../c.txt"),
+            ["a", "b", "c.txt"].iter().collect::<PathBuf>()
+        );
+        assert_eq!(
+            root.to_local_path("../../etc/passwd"),
+            ["etc", "passwd"].iter().collect::<PathBuf>()
+        );
+        assert_eq!(
+            root.to_local_path("a\\..\\b"),
+            ["a", "b"].iter().collect::<PathBuf>()
+        );
+        assert_eq!(root.to_local_path("/./"), PathBuf::new());
+        assert_eq!(
+            S3Path::root(':').to_local_path("a:..:b"),
+            ["a", "b"].iter().collect::<PathBuf>()
+        );
+    }
+}
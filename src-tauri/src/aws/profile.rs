@@ -1,19 +1,48 @@
 use std::{
     collections::HashMap,
-    io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write},
+    io::{Error, ErrorKind},
+    path::PathBuf,
+};
+
+use super::{
+    credentials::vault,
+    parser::{self, FileKind},
 };
 
 pub const CONFIG_FILE_NAME: &str = "config";
 pub const CREDENTIAL_FILE_NAME: &str = "credentials";
 
+// ファイルのパスを指定する環境変数
+const CONFIG_FILE_ENV: &str = "AWS_CONFIG_FILE";
+const CREDENTIAL_FILE_ENV: &str = "AWS_SHARED_CREDENTIALS_FILE";
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct AWSProfile {
     pub name: String,
-    pub region: String,
-    pub output: String,
+    pub region: Option<String>,
+    pub output: Option<String>,
     pub access_key_id: Option<String>,
     pub secret_access_key: Option<String>,
     pub session_token: Option<String>,
+    pub role_arn: Option<String>,
+    pub source_profile: Option<String>,
+    pub external_id: Option<String>,
+    pub role_session_name: Option<String>,
+    pub duration_seconds: Option<i32>,
+    pub mfa_serial: Option<String>,
+    pub sso: Option<SsoConfig>,
+    pub credential_process: Option<String>,
+}
+
+/// AWS SSO(IAM Identity Center)の設定
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct SsoConfig {
+    // `sso-session`セクションを参照している場合のセッション名
+    pub session_name: Option<String>,
+    pub start_url: String,
+    pub region: String,
+    pub account_id: String,
+    pub role_name: String,
 }
 
 pub trait AWSConfigManager<T> {
@@ -21,23 +50,77 @@ pub trait AWSConfigManager<T> {
 }
 
 struct Config {
-    region: String,
-    output: String,
+    region: Option<String>,
+    output: Option<String>,
+    role_arn: Option<String>,
+    source_profile: Option<String>,
+    external_id: Option<String>,
+    role_session_name: Option<String>,
+    duration_seconds: Option<i32>,
+    mfa_serial: Option<String>,
+    sso: Option<SsoConfig>,
+    credential_process: Option<String>,
 }
 
 struct Configs {
     items: HashMap<String, Config>,
 }
 
+/// プロファイルとそれが参照する`sso-session`セクションからSSOの設定を取得
+fn sso_config(
+    ele: &HashMap<String, String>,
+    sessions: &HashMap<String, HashMap<String, String>>,
+) -> Option<SsoConfig> {
+    let session_name = get_value_from_map(ele, "sso_session");
+    let session = session_name.as_ref().and_then(|n| sessions.get(n));
+
+    // プロファイルの値を優先し、未設定の場合は`sso-session`の値を使用
+    let value = |key: &str| {
+        get_value_from_map(ele, key).or_else(|| session.and_then(|s| get_value_from_map(s, key)))
+    };
+
+    Some(SsoConfig {
+        session_name: session_name.clone(),
+        start_url: value("sso_start_url")?,
+        region: value("sso_region")?,
+        account_id: get_value_from_map(ele, "sso_account_id")?,
+        role_name: get_value_from_map(ele, "sso_role_name")?,
+    })
+}
+
+/// HashMapからOption型で取り出す
+fn get_value_from_map(map: &HashMap<String, String>, key: &str) -> Option<String> {
+    map.get(key).cloned()
+}
+
 impl AWSConfigManager<Config> for Configs {
     fn new(val: HashMap<String, HashMap<String, String>>) -> Self {
+        // `sso-session`セクションはプロファイルではないため分けておく
+        let session_prefix = format!("{} ", parser::SSO_SESSION_PREFIX);
+        let (sessions, profiles): (HashMap<_, _>, HashMap<_, _>) = val
+            .into_iter()
+            .partition(|(key, _)| key.starts_with(&session_prefix));
+        let sessions = sessions
+            .into_iter()
+            .map(|(key, ele)| (key[session_prefix.len()..].to_string(), ele))
+            .collect::<HashMap<String, HashMap<String, String>>>();
+
         let mut items = HashMap::<String, Config>::new();
-        for (key, ele) in val {
+        for (key, ele) in profiles {
             items.insert(
-                key.clone().replace("profile ", ""),
+                key,
                 Config {
-                    region: ele["region"].clone(),
-                    output: ele["output"].clone(),
+                    region: get_value_from_map(&ele, "region"),
+                    output: get_value_from_map(&ele, "output"),
+                    role_arn: get_value_from_map(&ele, "role_arn"),
+                    source_profile: get_value_from_map(&ele, "source_profile"),
+                    external_id: get_value_from_map(&ele, "external_id"),
+                    role_session_name: get_value_from_map(&ele, "role_session_name"),
+                    duration_seconds: get_value_from_map(&ele, "duration_seconds")
+                        .and_then(|v| v.parse::<i32>().ok()),
+                    mfa_serial: get_value_from_map(&ele, "mfa_serial"),
+                    sso: sso_config(&ele, &sessions),
+                    credential_process: get_value_from_map(&ele, "credential_process"),
                 },
             );
         }
@@ -56,23 +139,16 @@ struct Credentials {
     items: HashMap<String, Credential>,
 }
 
-impl Credentials {
-    /// HashMapからOption型で取り出す
-    fn get_value_from_map(map: &HashMap<String, String>, key: &str) -> Option<String> {
-        map.get(key).cloned()
-    }
-}
-
 impl AWSConfigManager<Credential> for Credentials {
     fn new(val: HashMap<String, HashMap<String, String>>) -> Self {
         let mut items = HashMap::<String, Credential>::new();
         for (key, ele) in val {
             items.insert(
-                key.clone().replace("profile ", ""),
+                key,
                 Credential {
-                    access_key_id: Self::get_value_from_map(&ele, "aws_access_key_id"),
-                    secret_access_key: Self::get_value_from_map(&ele, "aws_secret_access_key"),
-                    session_token: Self::get_value_from_map(&ele, "aws_session_token"),
+                    access_key_id: get_value_from_map(&ele, "aws_access_key_id"),
+                    secret_access_key: get_value_from_map(&ele, "aws_secret_access_key"),
+                    session_token: get_value_from_map(&ele, "aws_session_token"),
                 },
             );
         }
@@ -80,168 +156,142 @@ impl AWSConfigManager<Credential> for Credentials {
     }
 }
 
+/// config, credentialファイルの読み込みエラー
+#[derive(Debug)]
+pub struct ReadError {
+    pub kind: FileKind,
+    source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl ReadError {
+    fn new(kind: FileKind, source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        ReadError {
+            kind,
+            source: source.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let file_name = match self.kind {
+            FileKind::Config => CONFIG_FILE_NAME,
+            FileKind::Credentials => CREDENTIAL_FILE_NAME,
+        };
+        write!(f, "{} file: {}", file_name, self.source)
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
 // AWS Config, Credentialファイル読み込み
-pub fn read_profiles() -> HashMap<String, AWSProfile> {
+// どちらかのファイルを読み込めない場合は一部のプロファイルのみ返却せずエラーとする
+pub fn read_profiles() -> Result<HashMap<String, AWSProfile>, ReadError> {
     // configファイル読み込み
-    let configs = match read::<Configs, Config>(CONFIG_FILE_NAME) {
-        Ok(v) => v.items,
-        Err(_) => HashMap::<String, Config>::new(),
-    };
+    let configs = read::<Configs, Config>(FileKind::Config)?.items;
 
     // credentialファイル読み込み
-    let credentials = match read::<Credentials, Credential>(CREDENTIAL_FILE_NAME) {
-        Ok(v) => v.items,
-        Err(_) => HashMap::<String, Credential>::new(),
-    };
+    let credentials = read::<Credentials, Credential>(FileKind::Credentials)?.items;
 
     // 返却用のリストを生成
     let mut list = HashMap::<String, AWSProfile>::new();
 
-    // configファイルから生成する
-    for (key, val) in configs {
-        // credentialの情報を取得
-        let (mut access_key_id, mut secret_access_key, mut session_token): (
-            Option<String>,
-            Option<String>,
-            Option<String>,
-        ) = (None, None, None);
-
-        // 同一プロファイル名のCredentialを取得
-        if let Some(cred) = credentials.get(&key) {
-            access_key_id = cred.access_key_id.clone();
-            secret_access_key = cred.secret_access_key.clone();
-            session_token = cred.session_token.clone();
+    // configファイル、credentialファイルのどちらかに存在するプロファイルを対象とする
+    let mut names = configs.keys().cloned().collect::<Vec<String>>();
+    for key in credentials.keys() {
+        if !configs.contains_key(key) {
+            names.push(key.clone());
         }
+    }
+    // 保管庫のみに認証情報が存在するプロファイルも対象とする
+    for key in vault::profile_names() {
+        if !names.contains(&key) {
+            names.push(key);
+        }
+    }
+
+    for key in names {
+        let val = configs.get(&key);
+        let cred = credentials.get(&key);
         list.insert(
             key.clone(),
             AWSProfile {
                 name: key,
-                region: val.region,
-                output: val.output,
-                access_key_id,
-                secret_access_key,
-                session_token,
+                region: val.and_then(|v| v.region.clone()),
+                output: val.and_then(|v| v.output.clone()),
+                access_key_id: cred.and_then(|c| c.access_key_id.clone()),
+                secret_access_key: cred.and_then(|c| c.secret_access_key.clone()),
+                session_token: cred.and_then(|c| c.session_token.clone()),
+                role_arn: val.and_then(|v| v.role_arn.clone()),
+                source_profile: val.and_then(|v| v.source_profile.clone()),
+                external_id: val.and_then(|v| v.external_id.clone()),
+                role_session_name: val.and_then(|v| v.role_session_name.clone()),
+                duration_seconds: val.and_then(|v| v.duration_seconds),
+                mfa_serial: val.and_then(|v| v.mfa_serial.clone()),
+                sso: val.and_then(|v| v.sso.clone()),
+                credential_process: val.and_then(|v| v.credential_process.clone()),
             },
         );
     }
 
-    list
+    Ok(list)
+}
+
+/// ファイルのパスを取得
+/// 環境変数でパスが指定されている場合はそちらを優先する
+pub fn file_path(kind: FileKind) -> Option<PathBuf> {
+    let (file_name, env_key) = match kind {
+        FileKind::Config => (CONFIG_FILE_NAME, CONFIG_FILE_ENV),
+        FileKind::Credentials => (CREDENTIAL_FILE_NAME, CREDENTIAL_FILE_ENV),
+    };
+
+    // 環境変数の指定がある場合
+    if let Ok(p) = std::env::var(env_key) {
+        if !p.trim().is_empty() {
+            // `~`で始まる場合はホームディレクトリに置換
+            return match p.trim().strip_prefix("~/") {
+                Some(rest) => dirs::home_dir().map(|home| home.join(rest)),
+                None => Some(PathBuf::from(p.trim())),
+            };
+        }
+    }
+
+    // ホームディレクトリ配下の`.aws`ディレクトリ
+    let mut path = dirs::home_dir()?;
+    path.push(".aws");
+    path.push(file_name);
+    Some(path)
 }
 
 /// ファイル読み込み
-pub fn read<T, S>(file_name: &str) -> Result<T, Box<dyn std::error::Error>>
+pub fn read<T, S>(kind: FileKind) -> Result<T, ReadError>
 where
     T: AWSConfigManager<S>,
 {
-    // ホームディレクトリを取得
-    let opt_home = dirs::home_dir();
-    // ホームディレクトリが存在しない場合は作成するかの確認メッセージを表示
-    if opt_home.is_none() {
-        return Err(Box::new(Error::new(
-            ErrorKind::Other,
-            "Oops... does not home directory..orz",
-        )));
-    }
-    // awsディレクトリを生成
-    let mut aws_dir = opt_home.unwrap();
-    aws_dir.push(".aws");
-
-    // 存在確認を行い、存在しない場合は作成するかの確認メッセージを表示
-    if !aws_dir.exists() {
-        return Err(Box::new(Error::new(
-            ErrorKind::Other,
-            "Oops... does not [~/.aws] directory..orz",
-        )));
-    }
+    // ファイルのパスを取得
+    // ホームディレクトリが存在しない場合はエラー
+    let config_path = match file_path(kind) {
+        Some(p) => p,
+        None => {
+            return Err(ReadError::new(
+                kind,
+                Error::new(ErrorKind::Other, "Oops... does not home directory..orz"),
+            ))
+        }
+    };
 
-    // ファイルのパスを生成
-    let mut config_path = aws_dir;
-    config_path.push(file_name);
-    // ファイルの存在確認し、存在しない場合は空で作成しておく
+    // ファイルが存在しない場合はプロファイルなしとして扱う
     if !config_path.exists() {
-        let mut f = BufWriter::new(std::fs::File::create(config_path.clone())?);
-        f.write_all("".as_bytes())?;
+        return Ok(T::new(HashMap::new()));
     }
 
     // ファイルを読み込み
-    let file = std::fs::File::open(config_path)?;
-    let reader = BufReader::new(file);
-    // 読み込んだファイルをHashMap形式に整形
-    let result = shape_aws_toml(reader)?;
-    Ok(T::new(result))
-}
-
-/// AWS関連のファイルデータをMap形式に整形する
-fn shape_aws_toml(
-    reader: BufReader<std::fs::File>,
-) -> std::io::Result<HashMap<String, HashMap<String, String>>> {
-    // 読み込んだデータ用のマップ
-    let mut tomls = HashMap::new();
-
-    // プロファイルの名称を取得するための正規表現
-    let re = regex::Regex::new(r"\[(.*)\]").unwrap();
-
-    // 現在処理中のキー名と格納用マップ
-    let mut key_name: Option<String> = None;
-
-    // バッファから1行ずつ読み込んで処理する
-    for line in reader.lines() {
-        // ライン読み込み
-        let l = line?;
-
-        // トリムした結果、空行であった場合は無視
-        if l.as_str().trim().is_empty() {
-            continue;
-        }
-
-        // 名称の行と一致する場合
-        if let Some(name) = re.captures(l.as_str()) {
-            let key = name.get(1).unwrap().as_str().to_string();
-            key_name = Some(key.clone());
-            tomls.insert(key, "".to_string());
-            continue;
-        }
-
-        // `=`で分割する
-        let c: char = '=';
-        let data = l.split(c).collect::<Vec<&str>>();
-        // 分割できなかった場合は無視
-        if data.len() < 2 {
-            continue;
-        }
-        let key = key_name.clone().unwrap();
-        if let Some(x) = tomls.get_mut(&key) {
-            // 未設定状態の場合はそのまま設定
-            *x = if x.is_empty() {
-                data.join("=")
-            } else {
-                let v = vec![x.clone(), data.join("=")];
-                v.join(";")
-            }
-        }
-    }
-
-    // 返却用のMapを生成
-    let mut ret_map = HashMap::new();
-    // 読み込んだデータのキー分実施
-    for (key, val) in tomls.iter() {
-        // キー内の要素を格納するMap
-        let mut data_map = HashMap::new();
-        let c: char = '=';
-        let c2: char = ';';
-        // セミコロンで分割することでキー要素内の個々のデータを取得する
-        val.split(c2).for_each(|x| {
-            // 個々のデータをKey, Value形式に変換してMapに追加
-            let v = x
-                .split(c)
-                .map(|s| s.trim().to_string())
-                .collect::<Vec<String>>();
-            // Base64変換された認証情報は値に`=`が含まれるため、すべてをjoinするようにする
-            let s = v[1..(v.len())].to_vec().join("=");
-            data_map.insert(v[0].clone(), s);
-        });
-        ret_map.insert(key.clone(), data_map);
-    }
-
-    Ok(ret_map)
+    let text = std::fs::read_to_string(config_path).map_err(|err| ReadError::new(kind, err))?;
+    // 読み込んだファイルを解析してHashMap形式に整形
+    let sections = parser::parse(&text).map_err(|err| ReadError::new(kind, err))?;
+    Ok(T::new(parser::normalize(&sections, kind)))
 }
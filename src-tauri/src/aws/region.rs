@@ -0,0 +1,182 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use once_cell::sync::Lazy;
+
+/// 何も指定がない場合に使用するリージョン
+pub const DEFAULT_REGION: &str = "ap-northeast-1";
+
+// 環境変数から参照するリージョン名のキー
+const REGION_ENV_KEYS: [&str; 2] = ["AWS_REGION", "AWS_DEFAULT_REGION"];
+
+/// リージョンの取得元
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionSource {
+    Override,
+    Profile,
+    Environment,
+    Default,
+}
+
+/// 解決済みのリージョン情報
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ResolvedRegion {
+    pub name: String,
+    pub source: RegionSource,
+}
+
+/// 上書き設定を正規化する
+/// 空文字の場合は上書きしない(`None`)
+pub fn normalize_override(region: Option<String>) -> Option<String> {
+    region
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty())
+}
+
+/// 上書き設定 → プロファイル → 環境変数 → デフォルトの順でリージョンを解決する
+/// 上書き設定はセッションごとに保持する(`None`の場合は上書きしない)
+/// `configured`はプロファイル(または接続設定)に設定されたリージョン
+pub fn resolve(overridden: Option<&str>, configured: Option<&str>) -> ResolvedRegion {
+    resolve_with(overridden, configured, |key| std::env::var(key).ok())
+}
+
+/// 上書き設定 → 指定のリージョン(プロファイル、接続設定) → 環境変数 → デフォルトの順でリージョンを解決する
+/// 環境変数は`env`から取得する
+pub fn resolve_with(
+    overridden: Option<&str>,
+    configured: Option<&str>,
+    env: impl Fn(&str) -> Option<String>,
+) -> ResolvedRegion {
+    let found = |r: Option<String>, source: RegionSource| {
+        r.map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .map(|name| ResolvedRegion { name, source })
+    };
+
+    // 上書き設定
+    if let Some(r) = found(overridden.map(|r| r.to_string()), RegionSource::Override) {
+        return r;
+    }
+
+    // プロファイル(接続設定)に設定されたリージョン
+    if let Some(r) = found(configured.map(|r| r.to_string()), RegionSource::Profile) {
+        return r;
+    }
+
+    // 環境変数
+    for key in REGION_ENV_KEYS {
+        if let Some(r) = found(env(key), RegionSource::Environment) {
+            return r;
+        }
+    }
+
+    ResolvedRegion {
+        name: DEFAULT_REGION.to_string(),
+        source: RegionSource::Default,
+    }
+}
+
+// バケット名とリージョンの対応を保持するキャッシュ
+// S3互換のエンドポイントにはAWSと同じ名前のバケットが存在するため、エンドポイントごとに保持する
+// (キーのエンドポイントが`None`の場合はAWS)
+static BUCKET_REGIONS: Lazy<RwLock<HashMap<(Option<String>, String), String>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// `GetBucketLocation`のロケーション制約をリージョン名に変換する
+/// us-east-1は空、eu-west-1は旧表記の`EU`で返却されるため補正する
+pub fn location_to_region(location: Option<&str>) -> String {
+    match location.map(|l| l.trim()) {
+        None | Some("") => "us-east-1".to_string(),
+        Some("EU") => "eu-west-1".to_string(),
+        Some(v) => v.to_string(),
+    }
+}
+
+/// キャッシュ済みのバケットのリージョンを取得
+/// `endpoint`はS3互換のエンドポイントのURL(AWSの場合は`None`)
+pub fn cached_bucket_region(endpoint: Option<&str>, bucket: &str) -> Option<String> {
+    BUCKET_REGIONS
+        .read()
+        .unwrap()
+        .get(&(endpoint.map(|e| e.to_string()), bucket.to_string()))
+        .cloned()
+}
+
+/// バケットのリージョンをキャッシュする
+/// `endpoint`はS3互換のエンドポイントのURL(AWSの場合は`None`)
+pub fn cache_bucket_region(endpoint: Option<&str>, bucket: &str, region: &str) {
+    BUCKET_REGIONS.write().unwrap().insert(
+        (endpoint.map(|e| e.to_string()), bucket.to_string()),
+        region.to_string(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_of(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |key| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn override_takes_precedence() {
+        let r = resolve_with(
+            Some("us-west-2"),
+            Some("eu-west-1"),
+            env_of(&[("AWS_REGION", "us-east-1")]),
+        );
+        assert_eq!(r.name, "us-west-2");
+        assert_eq!(r.source, RegionSource::Override);
+    }
+
+    #[test]
+    fn profile_is_used_without_override() {
+        let r = resolve_with(
+            None,
+            Some(" eu-west-1 "),
+            env_of(&[("AWS_REGION", "us-east-1")]),
+        );
+        assert_eq!(r.name, "eu-west-1");
+        assert_eq!(r.source, RegionSource::Profile);
+    }
+
+    #[test]
+    fn environment_is_used_without_profile() {
+        let r = resolve_with(
+            None,
+            None,
+            env_of(&[
+                ("AWS_REGION", "us-east-1"),
+                ("AWS_DEFAULT_REGION", "us-east-2"),
+            ]),
+        );
+        assert_eq!(r.name, "us-east-1");
+        assert_eq!(r.source, RegionSource::Environment);
+
+        let r = resolve_with(None, None, env_of(&[("AWS_DEFAULT_REGION", "us-east-2")]));
+        assert_eq!(r.name, "us-east-2");
+        assert_eq!(r.source, RegionSource::Environment);
+    }
+
+    #[test]
+    fn empty_values_fall_through_to_default() {
+        let r = resolve_with(Some(" "), Some(""), env_of(&[("AWS_REGION", "")]));
+        assert_eq!(r.name, DEFAULT_REGION);
+        assert_eq!(r.source, RegionSource::Default);
+    }
+
+    #[test]
+    fn normalize_override_clears_empty_value() {
+        assert_eq!(normalize_override(Some(" ".to_string())), None);
+        assert_eq!(
+            normalize_override(Some(" us-west-2 ".to_string())),
+            Some("us-west-2".to_string())
+        );
+        assert_eq!(normalize_override(None), None);
+    }
+}
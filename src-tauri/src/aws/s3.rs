@@ -5,10 +5,12 @@ use std::{
 };
 
 use aws_sdk_s3::{
-    error::ListBucketsError,
+    error::ListObjectsV2Error,
     model::{Delete, ObjectIdentifier},
+    output::ListObjectsV2Output,
     types::{ByteStream, SdkError},
 };
+use aws_smithy_types::retry::ProvideErrorKind;
 use platform_dirs::UserDirs;
 use tokio_stream::StreamExt;
 
@@ -17,6 +19,14 @@ use crate::{
     error::aws_error::{AwsError, AwsErrorKind},
 };
 
+use super::{path::S3Path, region, session::Session};
+
+// プレビューで取得する最大サイズ
+const PREVIEW_MAX_BYTES: i64 = 64 * 1024;
+
+// DeleteObjectsで一度に削除できる最大数
+const DELETE_BATCH_SIZE: usize = 1000;
+
 #[derive(serde::Serialize)]
 pub struct S3Bucket {
     pub name: String,
@@ -33,49 +43,177 @@ pub struct S3Object {
     pub is_folder: bool,
 }
 
+/// オブジェクトのプレビュー
+#[derive(serde::Serialize)]
+pub struct S3ObjectPreview {
+    pub key: String,
+    pub content_type: Option<String>,
+    // オブジェクト全体のサイズ
+    pub size: i64,
+    // 先頭から取得した内容(Base64)
+    pub data: String,
+    // 一部のみ取得した場合はtrue
+    pub truncated: bool,
+}
+
+/// オブジェクト一覧の取得結果
+/// 途中のページで失敗した場合は取得済みの一覧とエラーを返却する
+#[derive(serde::Serialize)]
+pub struct S3ObjectList {
+    pub objects: Vec<S3Object>,
+    pub totals: S3ObjectTotals,
+    // 2ページ目以降の取得に失敗した場合のエラー
+    pub error: Option<AwsError>,
+}
+
+/// 一覧に含まれるオブジェクトの件数、合計サイズ(フォルダは含まない)
+#[derive(Clone, Copy, Default, serde::Serialize)]
+pub struct S3ObjectTotals {
+    pub count: u64,
+    pub size: i64,
+}
+
+impl S3ObjectTotals {
+    /// 取得したオブジェクトを集計に加える
+    pub fn add(&mut self, objects: &[S3Object]) {
+        for obj in objects.iter().filter(|o| !o.is_folder) {
+            self.count += 1;
+            self.size += obj.size.unwrap_or_default();
+        }
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct S3OperationObject {
     pub prefix: String,
     pub is_folder: bool,
 }
 
-// クライアント生成
-async fn init_client() -> aws_sdk_s3::Client {
-    // aws config
-    let config = super::aws_config().await;
+// 指定のバケットが存在するリージョン向けのクライアントを取得
+async fn init_client(session: &Session, bucket_name: &str) -> aws_sdk_s3::Client {
+    let client = session.s3_client(None);
 
-    // S3 client
-    aws_sdk_s3::Client::new(&config)
+    // S3互換のエンドポイントの場合はリージョンの解決は行わない
+    if session.endpoint_url().is_some() {
+        return client;
+    }
+
+    // バケットのリージョンを解決し、設定中のリージョンと異なる場合は
+    // そのリージョン向けのクライアントを使用する
+    let current = session.region();
+    match bucket_region(
+        &client,
+        session.endpoint_url(),
+        bucket_name,
+        current.clone(),
+    )
+    .await
+    {
+        Some(r) if Some(&r) != current.as_ref() => session.s3_client(Some(r)),
+        _ => client,
+    }
+}
+
+// バケットのリージョンを取得する
+// キャッシュ → GetBucketLocation → HeadBucketのレスポンスヘッダの順で解決する
+async fn bucket_region(
+    client: &aws_sdk_s3::Client,
+    endpoint: Option<&str>,
+    bucket_name: &str,
+    current: Option<String>,
+) -> Option<String> {
+    // キャッシュ済みの場合はそのまま返却
+    if let Some(r) = region::cached_bucket_region(endpoint, bucket_name) {
+        return Some(r);
+    }
+
+    let resolved = match client
+        .get_bucket_location()
+        .bucket(bucket_name)
+        .send()
+        .await
+    {
+        Ok(v) => Some(region::location_to_region(
+            v.location_constraint().map(|l| l.as_str()),
+        )),
+        // 権限がない場合などはHeadBucketの`x-amz-bucket-region`から取得
+        Err(_) => match client.head_bucket().bucket(bucket_name).send().await {
+            // 成功した場合は現在のリージョンに存在する
+            Ok(_) => current,
+            Err(err) => region_from_error(&err),
+        },
+    };
+
+    if let Some(r) = &resolved {
+        region::cache_bucket_region(endpoint, bucket_name, r);
+    }
+    resolved
+}
+
+// 301などのエラーレスポンスのヘッダからバケットのリージョンを取得
+fn region_from_error<E>(err: &SdkError<E>) -> Option<String> {
+    let raw = match err {
+        SdkError::ServiceError { raw, .. } => raw,
+        SdkError::ResponseError { raw, .. } => raw,
+        _ => return None,
+    };
+    raw.http()
+        .headers()
+        .get("x-amz-bucket-region")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+/// SDKのエラーに操作対象のバケット、キーを設定して変換する
+pub(crate) fn sdk_error<E>(err: SdkError<E>, bucket_name: &str, key: Option<&str>) -> AwsError
+where
+    E: ProvideErrorKind + std::error::Error + Send + Sync + 'static,
+{
+    let error = AwsError::from_sdk(err).bucket(bucket_name);
+    match key {
+        Some(k) => error.key(k),
+        None => error,
+    }
 }
 
 /// S3 list buckets
-pub async fn list_buckets() -> Result<Vec<S3Bucket>, AwsError> {
+pub async fn list_buckets(session: &Session) -> Result<Vec<S3Bucket>, AwsError> {
+    let current = session.region();
+
     // S3 client
-    let client = init_client().await;
+    let conn = &session.connection;
+    let client = session.s3_client(None);
+
+    // 匿名アクセスの場合はバケット一覧を取得できないため、追加済みのバケットを返却
+    if let Some(c) = conn.as_ref().filter(|c| c.anonymous) {
+        // セッション開始後に追加されたバケットを含めるため設定を読み直す
+        let buckets = config::find_connection(&c.name)
+            .map(|x| x.buckets)
+            .unwrap_or_default();
+        let mut list = Vec::<S3Bucket>::new();
+        for name in &buckets {
+            let location = match &c.endpoint_url {
+                Some(_) => current.clone(),
+                None => bucket_region(&client, name, current.clone()).await,
+            };
+            list.push(S3Bucket {
+                name: name.clone(),
+                created_at: "".to_string(),
+                location: location.unwrap_or_default(),
+            });
+        }
+        return Ok(list);
+    }
 
     // S3 list bucket request
-    let res = client.list_buckets().send().await;
-    if res.is_err() {
-        let error: SdkError<ListBucketsError> = res.err().unwrap();
-        // エラーの種類によって返却するエラーを変更する
-        let kind = match error {
-            SdkError::ConstructionFailure(_) => AwsErrorKind::AccessDenied,
-            SdkError::TimeoutError(_) => AwsErrorKind::AccessDenied,
-            SdkError::DispatchFailure(_) => AwsErrorKind::AccessDenied,
-            SdkError::ResponseError { err: _, raw: _ } => AwsErrorKind::AccessDenied,
-            SdkError::ServiceError { err, raw: _ } => {
-                if err.code() == Some("ExpiredToken") {
-                    AwsErrorKind::ExpiredToken
-                } else {
-                    AwsErrorKind::AccessDenied
-                }
-            }
-        };
-        return Err(AwsError::new(kind));
-    }
+    // エラーの種類は画面側で判定する(SSOの再ログインや保管庫のロック解除など)
+    let result = client
+        .list_buckets()
+        .send()
+        .await
+        .map_err(AwsError::from_sdk)?;
 
     // レスポンスからバケット一覧を取得
-    let result = res.unwrap();
     let buckets = result.buckets().unwrap_or_default();
 
     // 返却するリスト
@@ -87,23 +225,21 @@ pub async fn list_buckets() -> Result<Vec<S3Bucket>, AwsError> {
         if let (Some(name), Some(creation_date)) = (bucket.name(), bucket.creation_date()) {
             // バケットの詳細情報を取得
             // ロケーション情報
-            let r = match client
-                .get_bucket_location()
-                .bucket(name.clone())
-                .send()
-                .await
-            {
-                Ok(v) => v,
-                Err(_) => return Err(AwsError::new(AwsErrorKind::NotFoundObject)),
+            // S3互換のエンドポイントではバケット名が一意ではないためキャッシュしない
+            let location = if conn.as_ref().map_or(false, |c| c.endpoint_url.is_some()) {
+                current.clone().unwrap_or_default()
+            } else {
+                match bucket_region(&client, name, current.clone()).await {
+                    Some(v) => v,
+                    None => return Err(AwsError::new(AwsErrorKind::NotFoundObject).bucket(name)),
+                }
             };
 
-            let location = r.location_constraint().unwrap().as_str();
-
             let created_at = super::parse_datetime(creation_date);
             list.push(S3Bucket {
                 name: name.to_string(),
                 created_at,
-                location: location.to_string(),
+                location,
             });
         }
     }
@@ -111,58 +247,177 @@ pub async fn list_buckets() -> Result<Vec<S3Bucket>, AwsError> {
     Ok(list)
 }
 
-/// 指定のパケットのオブジェクト一覧を取得
-pub async fn list_objects(
-    bucket_name: String,
-    prefix: Option<String>,
-) -> Result<Vec<S3Object>, aws_sdk_s3::Error> {
+/// バケット名を指定して匿名アクセスの接続設定に追加する
+/// 匿名アクセスではバケット一覧を取得できないため、アクセスできることを確認してから保存する
+pub async fn add_bucket(session: &Session, bucket_name: String) -> Result<S3Bucket, AwsError> {
+    let conn = session
+        .connection
+        .as_ref()
+        .filter(|c| c.anonymous)
+        .ok_or_else(|| {
+            AwsError::new(AwsErrorKind::InvalidArgument)
+                .message("anonymous connection is not selected")
+        })?;
+    let bucket_name = bucket_name.trim().to_string();
+    if bucket_name.is_empty() {
+        return Err(
+            AwsError::new(AwsErrorKind::InvalidArgument).message("bucket name must not be empty")
+        );
+    }
+
     // S3 client
-    let client = init_client().await;
+    let client = init_client(session, &bucket_name).await;
 
-    // バケットインスタンスを生成
-    let mut bucket = client
+    // 1件のみ取得してアクセスできるか確認
+    client
         .list_objects_v2()
-        .bucket(bucket_name.clone())
-        .delimiter("/");
+        .bucket(&bucket_name)
+        .max_keys(1)
+        .send()
+        .await
+        .map_err(|e| sdk_error(e, &bucket_name, None))?;
 
-    // プレフィックスの指定がある場合は設定
-    if let Some(p) = &prefix {
-        bucket = bucket.prefix(p);
+    config::add_connection_bucket(&conn.name, &bucket_name);
+
+    let location = match &conn.endpoint_url {
+        Some(_) => conn.region.clone(),
+        None => region::cached_bucket_region(None, &bucket_name),
+    };
+    Ok(S3Bucket {
+        name: bucket_name,
+        created_at: "".to_string(),
+        location: location.unwrap_or_default(),
+    })
+}
+
+/// 匿名アクセスの接続設定からバケットを削除する
+pub fn remove_bucket(session: &Session, bucket_name: &str) {
+    if let Some(conn) = &session.connection {
+        config::remove_connection_bucket(&conn.name, bucket_name);
     }
+}
+
+/// 指定のパケットのオブジェクト一覧を取得
+/// `recursive`の場合は配下のすべてのオブジェクトをプレフィックスからの相対キーで返却する
+pub async fn list_objects(
+    session: &Session,
+    bucket_name: String,
+    prefix: Option<String>,
+    recursive: bool,
+) -> Result<S3ObjectList, AwsError> {
+    // バケットに設定された区切り文字
+    let delimiter = session.delimiter(&bucket_name);
 
     // リクエスト
-    let mut stream = bucket.into_paginator().send();
-    // let objects = result.contents().unwrap_or_default();
+    let mut stream =
+        object_pages(session, &bucket_name, prefix.clone(), recursive, delimiter).await;
 
     // 取得した情報を格納するリスト
     let mut list = Vec::<S3Object>::new();
+    let mut totals = S3ObjectTotals::default();
+    let mut formatter = ObjectPageFormatter::new(prefix, recursive, delimiter);
 
-    // 置換するプレフィックス
-    let replace_prefix = match prefix {
-        Some(v) => {
-            println!("prefix?: {}", v);
-            if v.len() == 0 {
-                None
-            } else {
-                if v.ends_with("/") {
-                    Some(v)
-                } else {
-                    let r = format!("{}/", v);
-                    Some(r)
+    // 取得済みのページ数
+    let mut pages = 0;
+
+    while let Some(res) = stream.next().await {
+        let data = match res {
+            Ok(v) => v,
+            Err(err) => {
+                let err = sdk_error(err, &bucket_name, formatter.prefix());
+                // 1ページ目で失敗した場合はエラーとする
+                if pages == 0 {
+                    return Err(err);
                 }
+                // 2ページ目以降の場合は取得済みの一覧を返却する
+                return Ok(S3ObjectList {
+                    objects: list,
+                    totals,
+                    error: Some(err),
+                });
             }
-        }
-        None => None,
-    };
+        };
+        pages += 1;
+
+        let mut objects = formatter.format(&data);
+        totals.add(&objects);
+        list.append(&mut objects);
+    }
+
+    Ok(S3ObjectList {
+        objects: list,
+        totals,
+        error: None,
+    })
+}
 
+/// オブジェクト一覧をページ単位で取得するストリームを生成する
+/// 次のページは前のページを読み終えてから要求する
+/// `recursive`の場合は区切り文字を指定せず、配下のすべてのオブジェクトを取得する
+pub async fn object_pages(
+    session: &Session,
+    bucket_name: &str,
+    prefix: Option<String>,
+    recursive: bool,
+    delimiter: char,
+) -> impl tokio_stream::Stream<Item = Result<ListObjectsV2Output, SdkError<ListObjectsV2Error>>> + Unpin
+{
+    // S3 client
+    let client = init_client(session, bucket_name).await;
+
+    // バケットインスタンスを生成
+    let mut bucket = client.list_objects_v2().bucket(bucket_name);
+    if !recursive {
+        bucket = bucket.delimiter(delimiter.to_string());
+    }
+
+    // プレフィックスの指定がある場合は設定
+    // 同じ文字列で始まる別のフォルダを含めないよう、フォルダとして指定する
+    let folder = S3Path::folder(prefix.as_deref(), delimiter);
+    if !folder.is_root() {
+        bucket = bucket.prefix(folder.as_str());
+    }
+
+    bucket.into_paginator().send()
+}
+
+/// 取得したページを画面表示用のオブジェクト一覧に整形する
+/// 同じフォルダを重複して返却しないため、1つの一覧のページをまたいで使用する
+pub struct ObjectPageFormatter {
+    // 一覧の対象のフォルダ
+    folder: S3Path,
+    // 配下のすべてのオブジェクトを返却する
+    recursive: bool,
     // 設定済みの階層を格納しておく
-    let mut setted = HashMap::<String, bool>::new();
+    setted: HashMap<String, bool>,
+}
 
-    while let Some(res) = stream.next().await {
-        if res.is_err() {
-            break;
+impl ObjectPageFormatter {
+    pub fn new(prefix: Option<String>, recursive: bool, delimiter: char) -> Self {
+        ObjectPageFormatter {
+            folder: S3Path::folder(prefix.as_deref(), delimiter),
+            recursive,
+            setted: HashMap::new(),
+        }
+    }
+
+    /// 一覧の対象のプレフィックス(末尾は区切り文字)
+    /// バケットの直下の場合は`None`
+    pub fn prefix(&self) -> Option<&str> {
+        if self.folder.is_root() {
+            None
+        } else {
+            Some(self.folder.as_str())
+        }
+    }
+
+    /// 1ページ分のオブジェクトを整形する
+    pub fn format(&mut self, data: &ListObjectsV2Output) -> Vec<S3Object> {
+        if self.recursive {
+            return self.format_recursive(data);
         }
-        let data = res.unwrap();
+
+        let mut list = Vec::<S3Object>::new();
         let objects = data.contents().unwrap_or_default();
 
         // 取得した情報を整形
@@ -172,24 +427,19 @@ pub async fn list_objects(
                 (obj.key(), obj.last_modified(), obj.storage_class())
             {
                 // プレフィックスが指定されている場合はその部分を除去
-                let key_prefix = if let Some(p) = &replace_prefix {
-                    key.replace(p.as_str(), "")
-                } else {
-                    key.to_string()
+                let key_prefix = match self.folder.sibling(key).strip_prefix(&self.folder) {
+                    Some(v) => v.to_string(),
+                    None => continue,
                 };
 
-                println!("key: {}", key_prefix);
-
-                // 置換した結果、空となった場合は無視
+                // 除去した結果、空となった場合(フォルダ自体)は無視
                 if key_prefix.is_empty() {
                     continue;
                 }
 
-                // 区切り文字でsplitした最初のアイテムのみ返却する
-                let item = key_prefix.split("/").into_iter().collect::<Vec<&str>>();
-
-                // プレフィックスが指定されている場合は1番目の値を取得する
-                let data = item[0].to_string();
+                // 区切り文字で区切った最初のセグメントのみ返却する
+                let (first, in_folder) = self.folder.first_segment(&key_prefix);
+                let data = first.to_string();
 
                 // アイテムを生成する
                 let mut s3_object = S3Object {
@@ -200,21 +450,24 @@ pub async fn list_objects(
                     is_folder: false,
                 };
 
-                // キーの一番最後の値は"/"の場合はディレクトリと判定する
-                if key_prefix.ends_with("/") && obj.size == 0 && setted.get(&data).is_none() {
+                // キーの一番最後の値が区切り文字の場合はディレクトリと判定する
+                if key_prefix.ends_with(self.folder.delimiter())
+                    && obj.size == 0
+                    && self.setted.get(&data).is_none()
+                {
                     s3_object.is_folder = true;
                     list.push(s3_object);
-                    setted.insert(data, true);
+                    self.setted.insert(data, true);
                     continue;
                 }
 
                 // ディレクトリ内のデータの場合は無視
-                if item.len() > 1 {
+                if in_folder {
                     // ディレクトリの第一階層の場合は追加しておく
-                    if setted.get(&data).is_none() {
+                    if self.setted.get(&data).is_none() {
                         s3_object.is_folder = true;
                         list.push(s3_object);
-                        setted.insert(data, true);
+                        self.setted.insert(data, true);
                     }
                     continue;
                 }
@@ -225,15 +478,14 @@ pub async fn list_objects(
 
         // フォルダについては`delimiter`を設定すると`common_prefix`に含まれるため、
         // そこから取得する
-        let cm_prefix = data.common_prefixes.unwrap_or_default();
+        let cm_prefix = data.common_prefixes().unwrap_or_default();
         for obj in cm_prefix {
             // プレフィックスが設定されている場合のみ
-            if let Some(p) = obj.prefix {
+            if let Some(p) = obj.prefix() {
                 // プレフィックスが指定されている場合はその部分を除去
-                let key_prefix = if let Some(rp) = &replace_prefix {
-                    p.replace(rp.as_str(), "")
-                } else {
-                    p.to_string()
+                let key_prefix = match self.folder.sibling(p).strip_prefix(&self.folder) {
+                    Some(v) => v.to_string(),
+                    None => continue,
                 };
 
                 let s3_object = S3Object {
@@ -244,42 +496,68 @@ pub async fn list_objects(
                     is_folder: true,
                 };
 
-                if setted.get(&key_prefix).is_none() {
+                if self.setted.get(&key_prefix).is_none() {
                     list.push(s3_object);
-                    setted.insert(key_prefix, true);
+                    self.setted.insert(key_prefix, true);
                     continue;
                 }
             }
         }
+
+        list
     }
 
-    Ok(list)
+    // 配下のすべてのオブジェクトをフォルダからの相対キーで整形する
+    // フォルダを表す空のオブジェクト(末尾が区切り文字)はフォルダとして返却する
+    fn format_recursive(&self, data: &ListObjectsV2Output) -> Vec<S3Object> {
+        let objects = data.contents().unwrap_or_default();
+        objects
+            .iter()
+            .filter_map(|obj| {
+                let key = self.folder.sibling(obj.key()?);
+                let relative = key.strip_prefix(&self.folder)?;
+                if relative.is_empty() {
+                    return None;
+                }
+                Some(S3Object {
+                    key: relative.to_string(),
+                    last_modified: obj.last_modified().map(super::parse_datetime),
+                    size: Some(obj.size),
+                    storage_class: obj.storage_class().map(|c| c.as_str().to_string()),
+                    is_folder: key.is_folder(),
+                })
+            })
+            .collect()
+    }
 }
 
 // 指定のオブジェクトを削除
 pub async fn delete_objects(
+    session: &Session,
     bucket_name: String,
     objects: Vec<S3OperationObject>,
-) -> Result<(), aws_sdk_s3::Error> {
+) -> Result<(), AwsError> {
     // S3 client
-    let client = init_client().await;
+    let client = init_client(session, &bucket_name).await;
+    let delimiter = session.delimiter(&bucket_name);
 
     // 削除対象のリストを作成
     let mut dels: Vec<ObjectIdentifier> = vec![];
     for obj in objects {
         // フォルダであった場合は中のリストを取得
         if obj.is_folder {
-            // バケットインスタンスを生成
-            let bucket = client
-                .list_objects_v2()
-                .bucket(bucket_name.clone())
-                .prefix(obj.prefix);
-            // 指定パス内の情報を取得
-            let res = bucket.send().await?;
-            let objs = res.contents().unwrap_or_default();
-            for o in objs {
-                let obj_id = ObjectIdentifier::builder().set_key(o.key.clone()).build();
-                dels.push(obj_id);
+            // 同じ文字列で始まる別のフォルダを含めないよう、末尾を区切り文字とする
+            let folder = S3Path::new(obj.prefix, delimiter).to_folder();
+
+            // 指定パス配下のすべてのオブジェクトをページごとに取得
+            let prefix = Some(folder.as_str().to_string());
+            let mut pages = object_pages(session, &bucket_name, prefix, true, delimiter).await;
+            while let Some(res) = pages.next().await {
+                let data = res.map_err(|e| sdk_error(e, &bucket_name, Some(folder.as_str())))?;
+                for o in data.contents().unwrap_or_default() {
+                    let obj_id = ObjectIdentifier::builder().set_key(o.key.clone()).build();
+                    dels.push(obj_id);
+                }
             }
         } else {
             let obj_id = ObjectIdentifier::builder()
@@ -289,42 +567,74 @@ pub async fn delete_objects(
         }
     }
 
-    // 削除リクエスト作成
-    let delete = Delete::builder().set_objects(Some(dels.clone())).build();
-
-    // リクエスト
-    client
-        .delete_objects()
-        .bucket(bucket_name)
-        .delete(delete)
-        .send()
-        .await?;
-
-    println!("objects deleted. completed!: {:?}", dels);
+    // 1回のリクエストで削除できる件数ごとに削除する
+    for batch in dels.chunks(DELETE_BATCH_SIZE) {
+        // 削除リクエスト作成
+        let delete = Delete::builder().set_objects(Some(batch.to_vec())).build();
+
+        // リクエスト
+        let res = client
+            .delete_objects()
+            .bucket(&bucket_name)
+            .delete(delete)
+            .send()
+            .await
+            .map_err(|e| sdk_error(e, &bucket_name, None))?;
+
+        // リクエストが成功しても個別のオブジェクトの削除に失敗している場合がある
+        if let Some(err) = res.errors().unwrap_or_default().first() {
+            let mut error =
+                AwsError::from_service_code(err.code().unwrap_or_default()).bucket(&bucket_name);
+            if let Some(m) = err.message() {
+                error = error.message(m);
+            }
+            if let Some(k) = err.key() {
+                error = error.key(k);
+            }
+            return Err(error);
+        }
+        let deleted = res.deleted().unwrap_or_default().len();
+        if deleted < batch.len() {
+            return Err(AwsError::new(AwsErrorKind::Service)
+                .bucket(&bucket_name)
+                .message(format!(
+                    "{} of {} objects were not deleted",
+                    batch.len() - deleted,
+                    batch.len()
+                )));
+        }
+    }
 
     Ok(())
 }
 
 // 指定のオブジェクトをダウンロード
 pub async fn get_object(
+    session: &Session,
     bucket_name: String,
     object: S3OperationObject,
-) -> Result<String, aws_sdk_s3::Error> {
+) -> Result<String, AwsError> {
+    // キーの最後のセグメントをファイル名とする
+    let key = S3Path::new(object.prefix.as_str(), session.delimiter(&bucket_name));
+    let file_name = key.to_local_path(key.name());
+    if file_name.as_os_str().is_empty() {
+        return Err(AwsError::new(AwsErrorKind::InvalidArgument)
+            .message(format!("`{}` has no file name", object.prefix))
+            .bucket(&bucket_name)
+            .key(&object.prefix));
+    }
+
     // S3 client
-    let client = init_client().await;
+    let client = init_client(session, &bucket_name).await;
 
     // オブジェクトを取得
     let res = client
         .get_object()
-        .bucket(bucket_name)
+        .bucket(&bucket_name)
         .key(&object.prefix)
         .send()
-        .await?;
-
-    // プレフィックスからファイル名を取得
-    // 区切り文字でsplitした最後のアイテムのみ返却する
-    let item = object.prefix.split("/").into_iter().collect::<Vec<&str>>();
-    let file_name = item[item.len() - 1];
+        .await
+        .map_err(|e| sdk_error(e, &bucket_name, Some(&object.prefix)))?;
 
     // Configファイル読み込み
     let conf = config::read_config();
@@ -332,9 +642,68 @@ pub async fn get_object(
     // 指定がないユーザディレクトリからダウンロードディレクトリを取得
     let dwn_file = __download_path(&conf, None).join(file_name);
 
-    let _dwn_file = save_file(res.body, false, dwn_file).await;
+    let _dwn_file = save_file(res.body, false, dwn_file)
+        .await
+        .map_err(|e| e.bucket(&bucket_name).key(&object.prefix))?;
 
-    Ok(_dwn_file.as_path().to_str().unwrap().to_string())
+    Ok(_dwn_file.to_string_lossy().to_string())
+}
+
+// オブジェクトの先頭部分を取得してプレビューする
+pub async fn preview_object(
+    session: &Session,
+    bucket_name: String,
+    key: String,
+) -> Result<S3ObjectPreview, AwsError> {
+    // S3 client
+    let client = init_client(session, &bucket_name).await;
+
+    // 先頭から指定サイズのみ取得
+    let res = client
+        .get_object()
+        .bucket(&bucket_name)
+        .key(&key)
+        .range(format!("bytes=0-{}", PREVIEW_MAX_BYTES - 1))
+        .send()
+        .await;
+    let res = match res {
+        Ok(v) => v,
+        // 0バイトのオブジェクトは範囲を指定できない(416 InvalidRange)ため、範囲を指定せずに取得する
+        Err(SdkError::ServiceError { err, .. }) if err.code() == Some("InvalidRange") => client
+            .get_object()
+            .bucket(&bucket_name)
+            .key(&key)
+            .send()
+            .await
+            .map_err(|e| sdk_error(e, &bucket_name, Some(&key)))?,
+        Err(e) => return Err(sdk_error(e, &bucket_name, Some(&key))),
+    };
+
+    // `Content-Range: bytes 0-65535/12345678`からオブジェクト全体のサイズを取得
+    let size = res
+        .content_range()
+        .and_then(|r| r.rsplit('/').next())
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or_else(|| res.content_length());
+    let content_type = res.content_type().map(|t| t.to_string());
+    let body = res
+        .body
+        .collect()
+        .await
+        .map_err(|e| {
+            AwsError::custom(AwsErrorKind::Network, e)
+                .bucket(&bucket_name)
+                .key(&key)
+        })?
+        .into_bytes();
+
+    Ok(S3ObjectPreview {
+        key,
+        content_type,
+        size,
+        truncated: (body.len() as i64) < size,
+        data: base64::encode(&body),
+    })
 }
 
 fn __download_path(conf: &UserConfig, dir_name: Option<String>) -> PathBuf {
@@ -361,17 +730,21 @@ fn __download_path(conf: &UserConfig, dir_name: Option<String>) -> PathBuf {
 }
 
 // ファイルを保存
-async fn save_file(body: ByteStream, is_folder: bool, path: PathBuf) -> PathBuf {
-    let body = body.collect().await.unwrap().into_bytes();
+async fn save_file(body: ByteStream, is_folder: bool, path: PathBuf) -> Result<PathBuf, AwsError> {
+    let body = body
+        .collect()
+        .await
+        .map_err(|e| AwsError::custom(AwsErrorKind::Network, e))?
+        .into_bytes();
 
     // 保存するパス
     let mut p = path.clone();
 
-    println!("download file: {:?}", p.as_path());
-
     // フォルダの場合はダウンロード先のディレクトリを作成
     if is_folder {
-        std::fs::create_dir_all(p.parent().unwrap()).expect("could not create directory");
+        if let Some(parent) = p.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
     }
     // ファイルが存在するか確認し、存在する場合は別名のファイルを作成
     if p.exists() {
@@ -386,47 +759,37 @@ async fn save_file(body: ByteStream, is_folder: bool, path: PathBuf) -> PathBuf
     }
 
     // ファイルを作成
-    let mut file = std::fs::File::create(&p).expect("unable to create file");
-    let result = file.write_all(&body.to_vec());
-    if result.is_err() {
-        println!("file write error: {:?}", result.err());
-    }
+    let mut file = std::fs::File::create(&p)?;
+    file.write_all(&body)?;
 
-    return p;
+    Ok(p)
 }
 
 // フォルダ内のオブジェクトをすべてダウンロード
 pub async fn get_folder_object(
+    session: &Session,
     bucket_name: String,
     object: S3OperationObject,
-) -> Result<String, aws_sdk_s3::Error> {
+) -> Result<String, AwsError> {
     // Configファイル読み込み
     let conf = config::read_config();
 
     // S3 client
-    let client = init_client().await;
+    let client = init_client(session, &bucket_name).await;
 
-    // バケットインスタンスを生成
-    let bucket = client
-        .list_objects_v2()
-        .bucket(bucket_name.clone())
-        .prefix(&object.prefix);
-    // 指定パス内の情報を取得
-    let res = bucket.send().await?;
-    let objs = res.contents().unwrap_or_default();
+    // 同じ文字列で始まる別のフォルダを含めないよう、末尾を区切り文字とする
+    let delimiter = session.delimiter(&bucket_name);
+    let folder = S3Path::new(object.prefix.as_str(), delimiter).to_folder();
 
     // 指定されたプレフィックスが複数階層の場合は最後部分のみ取得して
-    // それをディレクトリとする
-    let item = object.prefix.split("/").into_iter().collect::<Vec<&str>>();
-    let mut dir_name = if object.prefix.ends_with("/") {
-        if item.len() > 1 {
-            item[item.len() - 2].to_string()
-        } else {
-            item[item.len() - 1].to_string()
-        }
-    } else {
-        item[item.len() - 1].to_string()
-    };
+    // それをディレクトリとする(バケットの直下の場合はバケット名)
+    let mut dir_name = folder
+        .to_local_path(folder.name())
+        .to_string_lossy()
+        .to_string();
+    if dir_name.is_empty() {
+        dir_name = bucket_name.clone();
+    }
 
     // ダウンロードディレクトリを取得
     let np = __download_path(&conf, None);
@@ -453,47 +816,44 @@ pub async fn get_folder_object(
         np.join(dir_name.clone())
     };
 
-    // 指定のオブジェクトを1つずつ保存する
-    for obj in objs {
-        if let Some(key) = obj.key.clone() {
-            // キーの一番最後の値が"/"の場合はディレクトリのため取得しない
-            if key.ends_with("/") {
-                continue;
-            }
-
-            // オブジェクトを取得
-            let out = client
-                .get_object()
-                .bucket(bucket_name.clone())
-                .key(key.clone())
-                .send()
-                .await?;
-
-            // ファイル名取得
-            let s = &key.split("/").into_iter().collect::<Vec<&str>>();
-            let file_name = s[s.len() - 1];
-
-            // ディレクトリを取得
-            let mut target_dir = save_dir.clone();
-            let key_prefix = &key.replace(&object.prefix, "");
-            let kps = key_prefix.split("/").into_iter().collect::<Vec<&str>>();
-            if kps.len() > 1 {
-                for i in 0..(kps.len() - 1) {
-                    target_dir = target_dir.clone().join(kps[i]);
-                    // ディレクトリを作成
-                    std::fs::create_dir_all(target_dir.clone())
-                        .expect("error create sub directory");
+    // 指定パス配下のすべてのオブジェクトをページごとに取得し、1つずつ保存する
+    let prefix = Some(folder.as_str().to_string());
+    let mut pages = object_pages(session, &bucket_name, prefix, true, delimiter).await;
+    while let Some(res) = pages.next().await {
+        let data = res.map_err(|e| sdk_error(e, &bucket_name, Some(folder.as_str())))?;
+        for obj in data.contents().unwrap_or_default() {
+            if let Some(key) = obj.key.clone() {
+                // キーの一番最後の値が区切り文字の場合はディレクトリのため取得しない
+                if folder.sibling(key.as_str()).is_folder() {
+                    continue;
                 }
-            }
-
-            // 指定がないユーザディレクトリからダウンロードディレクトリを取得
-            let dwn_file = target_dir.join(file_name);
-            println!("saveed: {:?}", dwn_file);
 
-            // ファイル保存
-            let dwn_file = save_file(out.body, true, dwn_file).await;
+                // フォルダからの相対パスをダウンロード先のパスとする
+                let relative = match folder.sibling(key.as_str()).strip_prefix(&folder) {
+                    Some(v) => folder.to_local_path(v),
+                    None => continue,
+                };
+                if relative.as_os_str().is_empty() {
+                    continue;
+                }
 
-            println!("downloaded: {:?}", dwn_file)
+                // オブジェクトを取得
+                let out = client
+                    .get_object()
+                    .bucket(bucket_name.clone())
+                    .key(key.clone())
+                    .send()
+                    .await
+                    .map_err(|e| sdk_error(e, &bucket_name, Some(&key)))?;
+
+                // 保存先のパス(サブディレクトリは保存時に作成する)
+                let dwn_file = save_dir.join(relative);
+
+                // ファイル保存
+                save_file(out.body, true, dwn_file)
+                    .await
+                    .map_err(|e| e.bucket(&bucket_name).key(&key))?;
+            }
         }
     }
 
@@ -501,7 +861,7 @@ pub async fn get_folder_object(
     if conf.dir_zip {
         // ZIPファイルを生成
         let zip_file_path = __download_path(&conf, Some(format!("{}.zip", &dir_name)));
-        let zip_file = std::fs::File::create(&zip_file_path).unwrap();
+        let zip_file = std::fs::File::create(&zip_file_path)?;
 
         // ディレクトリを取得
         let dir = save_dir.clone();
@@ -534,11 +894,9 @@ pub async fn get_folder_object(
 
             // ファイルの場合は読み込んでデータを追加
             if path.is_file() {
-                println!("adding file {:?} as {:?} ...", path, name);
-
                 // ファイル読み込み
-                let mut f = std::fs::File::open(path).expect("file open error");
-                f.read_to_end(&mut buffer).expect("file read error");
+                let mut f = std::fs::File::open(path)?;
+                f.read_to_end(&mut buffer)?;
 
                 // ファイルのメタデータから更新日時を取得
                 if let Ok(metadata) = f.metadata() {
@@ -554,22 +912,20 @@ pub async fn get_folder_object(
                 }
 
                 // ファイル書き込みを行う
-                zip.start_file(name, options).expect("start file error");
+                zip.start_file(name, options).map_err(AwsError::io)?;
 
                 // ZIPファイルに書き込み
-                zip.write_all(&*buffer).expect("file write error");
+                zip.write_all(&*buffer)?;
 
                 // バッファをクリア
                 buffer.clear();
             } else {
-                println!("adding dir {:?} as {:?} ...", path, name);
-                zip.add_directory(name, options)
-                    .expect("error zip add directory");
+                zip.add_directory(name, options).map_err(AwsError::io)?;
             }
         }
 
         // ZIPファイルクローズ
-        zip.flush().expect("error zip flush");
+        zip.flush()?;
 
         // ZIP化する前のディレクトリを削除
         let _ = std::fs::remove_dir_all(save_dir);
@@ -579,57 +935,73 @@ pub async fn get_folder_object(
 }
 
 // フォルダを作成
-pub async fn create_folder(bucket_name: String, prefix: String) -> Result<bool, aws_sdk_s3::Error> {
+pub async fn create_folder(
+    session: &Session,
+    bucket_name: String,
+    prefix: String,
+) -> Result<bool, AwsError> {
+    // フォルダとして作成するため末尾を区切り文字とする
+    let folder = S3Path::new(prefix, session.delimiter(&bucket_name)).to_folder();
+    if folder.is_root() {
+        return Err(AwsError::new(AwsErrorKind::InvalidArgument)
+            .message("folder name must not be empty")
+            .bucket(&bucket_name));
+    }
+
     // S3 client
-    let client = init_client().await;
+    let client = init_client(session, &bucket_name).await;
 
     // PutPbjectインスタンス生成
-    let req = client.put_object().bucket(bucket_name.clone()).key(prefix);
-    req.send().await?;
+    let req = client
+        .put_object()
+        .bucket(bucket_name.clone())
+        .key(folder.as_str());
+    req.send()
+        .await
+        .map_err(|e| sdk_error(e, &bucket_name, Some(folder.as_str())))?;
 
     Ok(true)
 }
 
 // ファイルをアップロード
 pub async fn put_object(
+    session: &Session,
     bucket_name: String,
     prefix: Option<String>,
     file_path: String,
-) -> Result<bool, aws_sdk_s3::Error> {
+) -> Result<bool, AwsError> {
     // S3 client
-    let client = init_client().await;
+    let client = init_client(session, &bucket_name).await;
 
     // PutPbjectインスタンス生成
     let mut req = client.put_object().bucket(bucket_name.clone());
 
     // ファイル名を取得
     let path_buf = PathBuf::from(&file_path);
-    let file_name = path_buf.file_name().unwrap().to_str().unwrap();
-
-    // プレフィックスの指定がある場合は設定
-    if let Some(p) = prefix {
-        if p.len() > 0 {
-            req = req.key(format!("{}/{}", p, file_name));
-        } else {
-            req = req.key(file_name);
-        }
-    } else {
-        req = req.key(file_name);
-    }
+    let file_name = path_buf
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| {
+            AwsError::new(AwsErrorKind::InvalidArgument)
+                .message(format!("invalid file path `{}`", file_path))
+        })?;
+
+    // プレフィックスの指定がある場合はその配下とする
+    let key = S3Path::folder(prefix.as_deref(), session.delimiter(&bucket_name))
+        .join(file_name)
+        .to_string();
+    req = req.key(&key);
 
     // アップロードするファイルのバイナリを生成
-    match ByteStream::from_path(Path::new(file_path.as_str())).await {
-        Ok(b) => {
-            req = req.body(b);
-            // ファイルアップロードリクエスト
-            req.send().await?;
-            println!("uploaded: file!");
-        }
-        Err(err) => {
-            println!("file open error: {}", err);
-            return Ok(false);
-        }
-    }
+    let body = ByteStream::from_path(Path::new(file_path.as_str()))
+        .await
+        .map_err(|e| AwsError::io(e).bucket(&bucket_name).key(&key))?;
+    req = req.body(body);
+
+    // ファイルアップロードリクエスト
+    req.send()
+        .await
+        .map_err(|e| sdk_error(e, &bucket_name, Some(&key)))?;
 
     Ok(true)
 }
@@ -0,0 +1,702 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use aws_sdk_s3::{model::Object, output::ListObjectsV2Output};
+use regex::{Regex, RegexBuilder};
+use tauri::{AppHandle, Manager};
+use tokio::sync::{mpsc, Semaphore};
+use tokio_stream::StreamExt;
+
+use super::{path::S3Path, s3, session::Session, task};
+use crate::error::aws_error::{AwsError, AwsErrorKind};
+
+/// 検索結果を通知するイベント名
+pub const SEARCH_PROGRESS_EVENT: &str = "search-progress";
+
+// 並行して検索するフォルダの数
+const PARALLEL_WALKERS: usize = 4;
+
+// 一致したオブジェクトがない場合に確認済みの件数を通知する間隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// パターンの種類
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternKind {
+    // `*`, `**`, `?`, `[...]`を使用するパターン
+    // 区切り文字を含まない場合はファイル名、含む場合はプレフィックスからの相対パスと比較する
+    Glob,
+    // キー全体のいずれかの部分に一致する正規表現
+    Regex,
+}
+
+impl Default for PatternKind {
+    fn default() -> Self {
+        PatternKind::Glob
+    }
+}
+
+/// 画面から指定された検索条件
+/// 未指定の条件は絞り込みを行わない
+#[derive(Clone, Default, serde::Deserialize)]
+pub struct SearchQuery {
+    pub prefix: Option<String>,
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub pattern_kind: PatternKind,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    // サイズの範囲(バイト、両端を含む)
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    // 更新日時の範囲(RFC3339、または`YYYY-MM-DD`)
+    // `modified_after`以降、`modified_before`より前
+    // 日付のみの場合はローカル時間で判定し、`modified_before`はその日を含む
+    pub modified_after: Option<String>,
+    pub modified_before: Option<String>,
+    // ストレージクラス(`STANDARD`, `GLACIER`など)
+    #[serde(default)]
+    pub storage_classes: Vec<String>,
+    // プレフィックス直下のフォルダごとに並行して検索する
+    #[serde(default)]
+    pub parallel: bool,
+}
+
+/// 条件に一致したオブジェクト
+#[derive(Clone, serde::Serialize)]
+pub struct SearchMatch {
+    // バケット内のキー(プレフィックスを含む)
+    pub key: String,
+    pub size: i64,
+    pub last_modified: Option<String>,
+    pub storage_class: String,
+}
+
+/// 画面に通知する検索の途中経過
+#[derive(Clone, serde::Serialize)]
+pub struct SearchProgress {
+    pub search_id: String,
+    pub bucket: String,
+    // 前回の通知以降に一致したオブジェクト
+    pub matches: Vec<SearchMatch>,
+    // 確認済みのオブジェクト数、一致したオブジェクト数(累計)
+    pub scanned: u64,
+    pub matched: u64,
+    // 検索が終了した場合はtrue(失敗した場合を含む)
+    pub done: bool,
+    // 失敗した場合のエラー(`AwsError`をシリアライズしたもの)
+    pub error: Option<serde_json::Value>,
+}
+
+/// 検索を開始し、1ページ確認するごとに一致したオブジェクトと確認済みの件数をイベントで通知する
+/// 一致したオブジェクトがないページは`PROGRESS_INTERVAL`ごとにまとめて通知する
+/// 戻り値の検索IDで通知の判別、取り消しを行う
+pub fn start(
+    app: AppHandle,
+    session: Arc<Session>,
+    bucket_name: String,
+    query: SearchQuery,
+) -> Result<String, AwsError> {
+    let delimiter = session.delimiter(&bucket_name);
+    let filter = Arc::new(Filter::new(&query, delimiter)?);
+
+    Ok(task::start("search", move |id| async move {
+        run(app, session, &id, bucket_name, query, filter).await;
+    }))
+}
+
+/// 実行中の検索を取り消す
+/// 取り消した後はイベントを通知しない
+pub fn cancel(search_id: &str) {
+    task::cancel(search_id)
+}
+
+// 1ページ分の検索結果
+struct Found {
+    matches: Vec<SearchMatch>,
+    scanned: u64,
+}
+
+// 検索を実行して結果を通知する
+async fn run(
+    app: AppHandle,
+    session: Arc<Session>,
+    search_id: &str,
+    bucket_name: String,
+    query: SearchQuery,
+    filter: Arc<Filter>,
+) {
+    // 各フォルダの検索結果を受け取る
+    // 取り消された場合は受信側が破棄され、検索中のタスクも次のページを要求せず終了する
+    let (tx, mut rx) = mpsc::channel::<Result<Found, AwsError>>(PARALLEL_WALKERS * 2);
+    let walker = if query.parallel {
+        tokio::spawn(walk_parallel(
+            session,
+            bucket_name.clone(),
+            filter.folder.clone(),
+            filter,
+            tx,
+        ))
+    } else {
+        tokio::spawn(walk(
+            session,
+            bucket_name.clone(),
+            filter.folder.clone(),
+            filter,
+            tx,
+        ))
+    };
+
+    let mut scanned = 0;
+    let mut matched = 0;
+    let mut error = None;
+    let mut notified = Instant::now();
+    while let Some(res) = rx.recv().await {
+        let found = match res {
+            Ok(v) => v,
+            // 他のフォルダの検索は継続し、最初のエラーのみ通知する
+            Err(err) => {
+                if error.is_none() {
+                    error = serde_json::to_value(&err).ok();
+                }
+                continue;
+            }
+        };
+        scanned += found.scanned;
+        matched += found.matches.len() as u64;
+        // 一致したオブジェクトがない場合も確認済みの件数は通知する(間隔を空ける)
+        if found.matches.is_empty() && notified.elapsed() < PROGRESS_INTERVAL {
+            continue;
+        }
+        notified = Instant::now();
+
+        let payload = SearchProgress {
+            search_id: search_id.to_string(),
+            bucket: bucket_name.clone(),
+            matches: found.matches,
+            scanned,
+            matched,
+            done: false,
+            error: None,
+        };
+        if let Err(err) = app.emit_all(SEARCH_PROGRESS_EVENT, payload) {
+            println!("emit {} error: {}", SEARCH_PROGRESS_EVENT, err);
+            walker.abort();
+            return;
+        }
+    }
+
+    // すべての検索が終了した場合
+    let payload = SearchProgress {
+        search_id: search_id.to_string(),
+        bucket: bucket_name,
+        matches: Vec::new(),
+        scanned,
+        matched,
+        done: true,
+        error,
+    };
+    if let Err(err) = app.emit_all(SEARCH_PROGRESS_EVENT, payload) {
+        println!("emit {} error: {}", SEARCH_PROGRESS_EVENT, err);
+    }
+}
+
+// 指定のフォルダ配下のすべてのオブジェクトを順に検索する
+async fn walk(
+    session: Arc<Session>,
+    bucket_name: String,
+    folder: S3Path,
+    filter: Arc<Filter>,
+    tx: mpsc::Sender<Result<Found, AwsError>>,
+) {
+    let prefix = Some(folder.as_str().to_string());
+    let mut stream =
+        s3::object_pages(&session, &bucket_name, prefix, true, folder.delimiter()).await;
+
+    while !tx.is_closed() {
+        let res = match stream.next().await {
+            Some(Ok(data)) => Ok(filter.page(&data)),
+            Some(Err(err)) => Err(s3::sdk_error(err, &bucket_name, Some(folder.as_str()))),
+            None => return,
+        };
+        let failed = res.is_err();
+        if tx.send(res).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+// 指定のフォルダ直下のオブジェクトを検索し、
+// 直下のフォルダはそれぞれ並行して検索する
+async fn walk_parallel(
+    session: Arc<Session>,
+    bucket_name: String,
+    folder: S3Path,
+    filter: Arc<Filter>,
+    tx: mpsc::Sender<Result<Found, AwsError>>,
+) {
+    let prefix = Some(folder.as_str().to_string());
+    let mut stream =
+        s3::object_pages(&session, &bucket_name, prefix, false, folder.delimiter()).await;
+
+    let semaphore = Arc::new(Semaphore::new(PARALLEL_WALKERS));
+    while let Some(res) = stream.next().await {
+        let data = match res {
+            Ok(v) => v,
+            Err(err) => {
+                let err = s3::sdk_error(err, &bucket_name, Some(folder.as_str()));
+                let _ = tx.send(Err(err)).await;
+                return;
+            }
+        };
+
+        // 直下のフォルダごとに検索を開始する
+        // 同時に検索するフォルダの数は制限する
+        for common in data.common_prefixes().unwrap_or_default() {
+            let sub = match common.prefix() {
+                Some(p) => folder.sibling(p),
+                None => continue,
+            };
+            let (session, bucket_name, filter, tx, semaphore) = (
+                session.clone(),
+                bucket_name.clone(),
+                filter.clone(),
+                tx.clone(),
+                semaphore.clone(),
+            );
+            tokio::spawn(async move {
+                let _permit = match semaphore.acquire_owned().await {
+                    Ok(v) => v,
+                    Err(_) => return,
+                };
+                // 待機中に取り消された場合は検索しない
+                if tx.is_closed() {
+                    return;
+                }
+                walk(session, bucket_name, sub, filter, tx).await;
+            });
+        }
+
+        // 直下のオブジェクト
+        if tx.send(Ok(filter.page(&data))).await.is_err() {
+            return;
+        }
+    }
+}
+
+// 検索条件
+struct Filter {
+    folder: S3Path,
+    pattern: Option<Pattern>,
+    min_size: Option<i64>,
+    max_size: Option<i64>,
+    // 更新日時の範囲(UNIX時間)
+    modified_after: Option<i64>,
+    modified_before: Option<i64>,
+    storage_classes: Vec<String>,
+}
+
+// コンパイル済みのパターン
+enum Pattern {
+    // ファイル名と比較する
+    Name(Regex),
+    // プレフィックスからの相対パスと比較する
+    Path(Regex),
+    // キー全体と比較する
+    Key(Regex),
+}
+
+impl Filter {
+    fn new(query: &SearchQuery, delimiter: char) -> Result<Self, AwsError> {
+        let invalid =
+            |message: String| AwsError::new(AwsErrorKind::InvalidArgument).message(message);
+
+        let pattern = match query.pattern.as_deref().filter(|p| !p.is_empty()) {
+            Some(p) => {
+                let source = match query.pattern_kind {
+                    PatternKind::Glob => glob_to_regex(p, delimiter),
+                    PatternKind::Regex => p.to_string(),
+                };
+                let regex = RegexBuilder::new(&source)
+                    .case_insensitive(!query.case_sensitive)
+                    .build()
+                    .map_err(|e| invalid(format!("invalid pattern `{}`: {}", p, e)))?;
+                Some(match query.pattern_kind {
+                    PatternKind::Glob if p.contains(delimiter) => Pattern::Path(regex),
+                    PatternKind::Glob => Pattern::Name(regex),
+                    PatternKind::Regex => Pattern::Key(regex),
+                })
+            }
+            None => None,
+        };
+
+        let parse =
+            |value: &Option<String>, end_of_day| match value.as_deref().filter(|v| !v.is_empty()) {
+                Some(v) => parse_time(v, end_of_day)
+                    .map(Some)
+                    .ok_or_else(|| invalid(format!("invalid date `{}`", v))),
+                None => Ok(None),
+            };
+
+        Ok(Filter {
+            folder: S3Path::folder(query.prefix.as_deref(), delimiter),
+            pattern,
+            min_size: query.min_size,
+            max_size: query.max_size,
+            modified_after: parse(&query.modified_after, false)?,
+            modified_before: parse(&query.modified_before, true)?,
+            storage_classes: query
+                .storage_classes
+                .iter()
+                .map(|c| c.to_uppercase())
+                .collect(),
+        })
+    }
+
+    // 1ページ分のオブジェクトを絞り込む
+    fn page(&self, data: &ListObjectsV2Output) -> Found {
+        let objects = data.contents().unwrap_or_default();
+        Found {
+            matches: objects.iter().filter_map(|o| self.matches(o)).collect(),
+            scanned: objects.len() as u64,
+        }
+    }
+
+    // 条件に一致する場合は画面に返却する形式に変換する
+    fn matches(&self, obj: &Object) -> Option<SearchMatch> {
+        let key = self.folder.sibling(obj.key()?);
+        // フォルダを表す空のオブジェクトは対象外
+        if key.is_folder() {
+            return None;
+        }
+
+        let matched = match &self.pattern {
+            Some(Pattern::Name(re)) => re.is_match(key.name()),
+            Some(Pattern::Path(re)) => key
+                .strip_prefix(&self.folder)
+                .map_or(false, |r| re.is_match(r)),
+            Some(Pattern::Key(re)) => re.is_match(key.as_str()),
+            None => true,
+        };
+        if !matched {
+            return None;
+        }
+
+        if self.min_size.map_or(false, |min| obj.size < min)
+            || self.max_size.map_or(false, |max| obj.size > max)
+        {
+            return None;
+        }
+
+        let modified = obj.last_modified().map(|d| d.secs());
+        if let Some(after) = self.modified_after {
+            if modified.map_or(true, |m| m < after) {
+                return None;
+            }
+        }
+        if let Some(before) = self.modified_before {
+            if modified.map_or(true, |m| m >= before) {
+                return None;
+            }
+        }
+
+        // ストレージクラスが返却されない場合はSTANDARDとして扱う
+        let storage_class = obj
+            .storage_class()
+            .map(|c| c.as_str().to_string())
+            .unwrap_or_else(|| "STANDARD".to_string());
+        if !self.storage_classes.is_empty() && !self.storage_classes.contains(&storage_class) {
+            return None;
+        }
+
+        Some(SearchMatch {
+            key: key.to_string(),
+            size: obj.size,
+            last_modified: obj.last_modified().map(super::parse_datetime),
+            storage_class,
+        })
+    }
+}
+
+// globのパターンを正規表現に変換する
+// `*`、`?`は区切り文字に一致せず、`**`は区切り文字を含めて一致する
+// `**/`は0個以上のフォルダに一致する
+fn glob_to_regex(glob: &str, delimiter: char) -> String {
+    let escaped_delimiter = regex::escape(&delimiter.to_string());
+    let not_delimiter = format!("[^{}]", escaped_delimiter);
+    let chars = glob.chars().collect::<Vec<char>>();
+
+    let mut re = String::from("^");
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') && chars.get(i + 2) == Some(&delimiter) => {
+                re.push_str(&format!("(?:.*{})?", escaped_delimiter));
+                i += 2;
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                re.push_str(".*");
+                i += 1;
+            }
+            '*' => re.push_str(&format!("{}*", not_delimiter)),
+            '?' => re.push_str(&not_delimiter),
+            '[' => {
+                // 閉じ括弧がない場合は文字として扱う
+                let end = chars[(i + 1)..]
+                    .iter()
+                    .skip(1)
+                    .position(|c| *c == ']')
+                    .map(|p| i + p + 2);
+                match end {
+                    Some(end) => {
+                        re.push('[');
+                        let mut class = &chars[(i + 1)..end];
+                        if class.first() == Some(&'!') {
+                            re.push('^');
+                            class = &class[1..];
+                        }
+                        for c in class {
+                            match c {
+                                '-' => re.push('-'),
+                                c => re.push_str(&regex::escape(&c.to_string())),
+                            }
+                        }
+                        re.push(']');
+                        i = end;
+                    }
+                    None => re.push_str(r"\["),
+                }
+            }
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    re.push('$');
+    re
+}
+
+// 日時の文字列をUNIX時間に変換する
+// 日付のみの場合はローカル時間の0時とし、`end_of_day`の場合は翌日の0時とする
+fn parse_time(value: &str, end_of_day: bool) -> Option<i64> {
+    use chrono::TimeZone;
+
+    if let Ok(d) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(d.timestamp());
+    }
+    let mut date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    if end_of_day {
+        date = date.succ_opt()?;
+    }
+    let local = chrono::Local.from_local_datetime(&date.and_hms(0, 0, 0));
+    local.earliest().map(|d| d.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_s3::model::ObjectStorageClass;
+    use aws_smithy_types::DateTime;
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn glob(pattern: &str, delimiter: char) -> Regex {
+        Regex::new(&glob_to_regex(pattern, delimiter)).unwrap()
+    }
+
+    fn filter(query: SearchQuery) -> Filter {
+        Filter::new(&query, '/').unwrap()
+    }
+
+    fn object(key: &str, size: i64) -> Object {
+        Object::builder().key(key).size(size).build()
+    }
+
+    fn local_secs(y: i32, m: u32, d: u32, h: u32) -> i64 {
+        chrono::Local.ymd(y, m, d).and_hms(h, 0, 0).timestamp()
+    }
+
+    #[test]
+    fn glob_wildcards_do_not_cross_the_delimiter() {
+        let re = glob("*.txt", '/');
+        assert!(re.is_match("a.txt"));
+        assert!(!re.is_match("a/b.txt"));
+
+        let re = glob("a?c", '/');
+        assert!(re.is_match("abc"));
+        assert!(!re.is_match("a/c"));
+
+        let re = glob("a/**", '/');
+        assert!(re.is_match("a/b/c.txt"));
+    }
+
+    #[test]
+    fn glob_double_star_matches_zero_or_more_folders() {
+        let re = glob("**/*.txt", '/');
+        assert!(re.is_match("a.txt"));
+        assert!(re.is_match("a/b/c.txt"));
+        assert!(!re.is_match("a/b/c.csv"));
+
+        let re = glob("a/**/b", '/');
+        assert!(re.is_match("a/b"));
+        assert!(re.is_match("a/x/y/b"));
+        assert!(!re.is_match("ab"));
+    }
+
+    #[test]
+    fn glob_character_classes() {
+        let re = glob("[!a]bc", '/');
+        assert!(re.is_match("xbc"));
+        assert!(!re.is_match("abc"));
+
+        let re = glob("[a-c]x", '/');
+        assert!(re.is_match("bx"));
+        assert!(!re.is_match("dx"));
+
+        // 先頭の`]`は閉じ括弧ではなく文字として扱う
+        let re = glob("[]]", '/');
+        assert!(re.is_match("]"));
+        assert!(!re.is_match("x"));
+    }
+
+    #[test]
+    fn glob_unclosed_bracket_is_literal() {
+        let re = glob("a[b", '/');
+        assert!(re.is_match("a[b"));
+        assert!(!re.is_match("ab"));
+    }
+
+    #[test]
+    fn glob_uses_the_bucket_delimiter() {
+        let re = glob("*.txt", ':');
+        assert!(re.is_match("a/b.txt"));
+        assert!(!re.is_match("a:b.txt"));
+
+        let re = glob("**:*.txt", ':');
+        assert!(re.is_match("b.txt"));
+        assert!(re.is_match("a:b:c.txt"));
+    }
+
+    #[test]
+    fn glob_without_delimiter_matches_name_and_with_delimiter_matches_path() {
+        let name = filter(SearchQuery {
+            prefix: Some("docs".to_string()),
+            pattern: Some("*.txt".to_string()),
+            ..Default::default()
+        });
+        assert!(name.matches(&object("docs/a/b.txt", 1)).is_some());
+        assert!(name.matches(&object("docs/a/b.csv", 1)).is_none());
+
+        let path = filter(SearchQuery {
+            prefix: Some("docs".to_string()),
+            pattern: Some("a/*.txt".to_string()),
+            ..Default::default()
+        });
+        assert!(path.matches(&object("docs/a/b.txt", 1)).is_some());
+        assert!(path.matches(&object("docs/a/c/b.txt", 1)).is_none());
+        assert!(path.matches(&object("docs/x/a/b.txt", 1)).is_none());
+    }
+
+    #[test]
+    fn pattern_is_case_insensitive_by_default() {
+        let insensitive = filter(SearchQuery {
+            pattern: Some("*.TXT".to_string()),
+            ..Default::default()
+        });
+        assert!(insensitive.matches(&object("a.txt", 1)).is_some());
+
+        let sensitive = filter(SearchQuery {
+            pattern: Some("*.TXT".to_string()),
+            case_sensitive: true,
+            ..Default::default()
+        });
+        assert!(sensitive.matches(&object("a.txt", 1)).is_none());
+    }
+
+    #[test]
+    fn folder_objects_are_excluded() {
+        let all = filter(SearchQuery::default());
+        assert!(all.matches(&object("a/", 0)).is_none());
+        assert!(all.matches(&object("a/b", 0)).is_some());
+    }
+
+    #[test]
+    fn size_bounds_are_inclusive() {
+        let f = filter(SearchQuery {
+            min_size: Some(10),
+            max_size: Some(20),
+            ..Default::default()
+        });
+        assert!(f.matches(&object("a", 9)).is_none());
+        assert!(f.matches(&object("a", 10)).is_some());
+        assert!(f.matches(&object("a", 20)).is_some());
+        assert!(f.matches(&object("a", 21)).is_none());
+    }
+
+    #[test]
+    fn date_bounds() {
+        let modified = |secs: i64| {
+            Object::builder()
+                .key("a")
+                .last_modified(DateTime::from_secs(secs))
+                .build()
+        };
+
+        let f = filter(SearchQuery {
+            modified_after: Some("2022-06-01T00:00:00Z".to_string()),
+            modified_before: Some("2022-06-30T00:00:00Z".to_string()),
+            ..Default::default()
+        });
+        let after = chrono::Utc.ymd(2022, 6, 1).and_hms(0, 0, 0).timestamp();
+        let before = chrono::Utc.ymd(2022, 6, 30).and_hms(0, 0, 0).timestamp();
+        assert!(f.matches(&modified(after - 1)).is_none());
+        assert!(f.matches(&modified(after)).is_some());
+        assert!(f.matches(&modified(before - 1)).is_some());
+        assert!(f.matches(&modified(before)).is_none());
+        // 更新日時が不明な場合は範囲外とする
+        assert!(f.matches(&object("a", 0)).is_none());
+
+        // 日付のみの場合はローカル時間で判定し、`modified_before`の日を含む
+        let f = filter(SearchQuery {
+            modified_after: Some("2022-06-01".to_string()),
+            modified_before: Some("2022-06-15".to_string()),
+            ..Default::default()
+        });
+        assert!(f.matches(&modified(local_secs(2022, 5, 31, 23))).is_none());
+        assert!(f.matches(&modified(local_secs(2022, 6, 1, 0))).is_some());
+        assert!(f.matches(&modified(local_secs(2022, 6, 15, 23))).is_some());
+        assert!(f.matches(&modified(local_secs(2022, 6, 16, 0))).is_none());
+    }
+
+    #[test]
+    fn invalid_date_is_rejected() {
+        let query = SearchQuery {
+            modified_before: Some("2022-13-01".to_string()),
+            ..Default::default()
+        };
+        assert!(Filter::new(&query, '/').is_err());
+    }
+
+    #[test]
+    fn missing_storage_class_is_standard() {
+        let standard = filter(SearchQuery {
+            storage_classes: vec!["standard".to_string()],
+            ..Default::default()
+        });
+        let found = standard.matches(&object("a", 1)).unwrap();
+        assert_eq!(found.storage_class, "STANDARD");
+
+        let glacier = filter(SearchQuery {
+            storage_classes: vec!["GLACIER".to_string()],
+            ..Default::default()
+        });
+        assert!(glacier.matches(&object("a", 1)).is_none());
+
+        let archived = Object::builder()
+            .key("a")
+            .storage_class(ObjectStorageClass::Glacier)
+            .build();
+        assert!(glacier.matches(&archived).is_some());
+    }
+}
@@ -0,0 +1,326 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use aws_types::{credentials::ProvideCredentials, Credentials, SdkConfig};
+
+use super::{
+    endpoint,
+    parser::FileKind,
+    profile,
+    region::{self, RegionSource, ResolvedRegion},
+};
+use crate::{
+    config::connection::Connection,
+    error::aws_error::{AwsError, AwsErrorKind},
+};
+
+// セッションIDの採番
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// リージョンごとのクライアント(`None`はセッションのリージョン)
+type ClientMap = HashMap<Option<String>, aws_sdk_s3::Client>;
+
+// AWS Configのキャッシュのキー(プロファイル名, リージョンの上書き設定)
+type ProfileKey = (Option<String>, Option<String>);
+
+/// プロファイル(または接続設定)ごとの接続
+/// 開始時の設定を保持するため、プロファイルを切り替えても実行中の操作には影響しない
+pub struct Session {
+    pub id: String,
+    // `None`の場合は環境情報のデフォルト値を使用
+    pub profile: Option<String>,
+    pub config: SdkConfig,
+    // S3互換のエンドポイントや匿名アクセスの接続設定
+    pub connection: Option<Connection>,
+    // セッションのみで有効なリージョンの上書き設定
+    pub region_override: Option<String>,
+    // 解決したリージョンとその取得元
+    resolved_region: ResolvedRegion,
+    // 同じプロファイルのセッション間で共有するクライアント
+    clients: Arc<Mutex<ClientMap>>,
+    // 生成時のキャッシュの世代
+    generation: u64,
+}
+
+/// 画面に返却するセッションの情報
+#[derive(Clone, serde::Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub profile: Option<String>,
+    pub region: Option<String>,
+    pub region_source: RegionSource,
+}
+
+impl Session {
+    fn new(id: String, key: ProfileKey, entry: ProfileEntry, generation: u64) -> Self {
+        let (profile, region_override) = key;
+        Session {
+            id,
+            profile,
+            config: entry.config,
+            connection: entry.connection,
+            region_override,
+            resolved_region: entry.region,
+            clients: entry.clients,
+            generation,
+        }
+    }
+
+    pub fn info(&self) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            profile: self.profile.clone(),
+            region: self.region(),
+            region_source: self.resolved_region.source,
+        }
+    }
+
+    /// セッションのリージョンとその取得元
+    pub fn resolved_region(&self) -> &ResolvedRegion {
+        &self.resolved_region
+    }
+
+    /// セッションのリージョン
+    pub fn region(&self) -> Option<String> {
+        self.config.region().map(|r| r.as_ref().to_string())
+    }
+
+    /// セッションで使用する認証情報を解決する
+    /// 認証情報を使用しない場合は`None`
+    pub async fn credentials(&self) -> Result<Option<Credentials>, AwsError> {
+        let provider = match self.config.credentials_provider() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        provider
+            .provide_credentials()
+            .await
+            .map(Some)
+            .map_err(AwsError::from_credentials)
+    }
+
+    /// バケットに設定された階層の区切り文字
+    /// 接続設定のバケットは接続設定ごとに設定を保持する
+    pub fn delimiter(&self, bucket: &str) -> char {
+        crate::config::bucket_delimiter(self.connection_name(), bucket)
+    }
+
+    /// バケットの階層の区切り文字を設定する(`None`の場合は`/`に戻す)
+    pub fn set_delimiter(&self, bucket: &str, delimiter: Option<char>) {
+        crate::config::set_bucket_delimiter(self.connection_name(), bucket, delimiter)
+    }
+
+    fn connection_name(&self) -> Option<&str> {
+        self.connection.as_ref().map(|c| c.name.as_str())
+    }
+
+    /// S3互換のエンドポイントのURL(AWSに接続する場合は`None`)
+    pub fn endpoint_url(&self) -> Option<&str> {
+        self.connection
+            .as_ref()
+            .and_then(|c| c.endpoint_url.as_deref())
+    }
+
+    /// 指定のリージョン向けのS3クライアントを取得する
+    /// 生成済みの場合は再利用する
+    pub fn s3_client(&self, region_name: Option<String>) -> aws_sdk_s3::Client {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(&region_name) {
+            return client.clone();
+        }
+
+        let client = match (&self.connection, &region_name) {
+            (Some(c), _) => endpoint::s3_client(&self.config, c, region_name.clone()),
+            (None, Some(r)) => {
+                let conf = aws_sdk_s3::config::Builder::from(&self.config)
+                    .region(aws_sdk_s3::Region::new(r.clone()))
+                    .build();
+                aws_sdk_s3::Client::from_conf(conf)
+            }
+            (None, None) => aws_sdk_s3::Client::new(&self.config),
+        };
+        clients.insert(region_name, client.clone());
+        client
+    }
+}
+
+// プロファイルごとのAWS Configとクライアント
+#[derive(Clone)]
+struct ProfileEntry {
+    config: SdkConfig,
+    connection: Option<Connection>,
+    region: ResolvedRegion,
+    clients: Arc<Mutex<ClientMap>>,
+}
+
+impl ProfileEntry {
+    async fn load(key: &ProfileKey) -> Result<Self, AwsError> {
+        let (profile, region_override) = key;
+        let region = super::profile_region(profile.as_deref(), region_override.as_deref())?;
+        let config = match profile {
+            Some(name) => super::profile_config(name, &region.name).await,
+            None => super::default_config(&region.name).await,
+        };
+        let connection = profile
+            .as_ref()
+            .and_then(|name| crate::config::find_connection(name));
+
+        Ok(ProfileEntry {
+            config,
+            connection,
+            region,
+            clients: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+}
+
+/// 開始済みのセッション(Tauriのmanaged stateとして保持する)
+/// AWS Configとクライアントはプロファイルとリージョンの上書き設定ごとにキャッシュし、
+/// AWSのconfig, credentialファイルが更新された場合や`invalidate`で破棄する
+#[derive(Default)]
+pub struct Sessions {
+    items: Mutex<HashMap<String, Arc<Session>>>,
+    profiles: Mutex<HashMap<ProfileKey, ProfileEntry>>,
+    // キャッシュの世代(破棄するたびに更新)
+    generation: AtomicU64,
+    // キャッシュ作成時のAWSのファイルの状態
+    file_stamp: Mutex<Vec<Option<(SystemTime, u64)>>>,
+}
+
+impl Sessions {
+    /// セッションを開始して登録する
+    pub async fn open(&self, profile: Option<String>) -> Result<SessionInfo, AwsError> {
+        let id = format!("session-{}", NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        let session = self.create(id, (profile, None)).await?;
+        let info = session.info();
+        self.items
+            .lock()
+            .unwrap()
+            .insert(session.id.clone(), Arc::new(session));
+        Ok(info)
+    }
+
+    /// IDからセッションを取得する
+    /// 設定が変更されている場合は同じプロファイルでセッションを作り直す
+    /// (実行中の操作は取得済みのセッションを保持するため影響しない)
+    pub async fn get(&self, id: &str) -> Result<Arc<Session>, AwsError> {
+        let session = self.registered(id)?;
+
+        self.check_files();
+        if session.generation == self.generation.load(Ordering::SeqCst) {
+            return Ok(session);
+        }
+
+        let key = (session.profile.clone(), session.region_override.clone());
+        self.replace(id, key).await
+    }
+
+    /// セッションのリージョンを上書きする
+    /// `None`または空文字の場合は上書きを解除し、プロファイルのリージョンに戻す
+    /// 他のセッションには影響しない
+    pub async fn set_region(
+        &self,
+        id: &str,
+        region_name: Option<String>,
+    ) -> Result<Arc<Session>, AwsError> {
+        let session = self.registered(id)?;
+        let key = (
+            session.profile.clone(),
+            region::normalize_override(region_name),
+        );
+        self.replace(id, key).await
+    }
+
+    // 登録済みのセッションを取得する
+    fn registered(&self, id: &str) -> Result<Arc<Session>, AwsError> {
+        self.items.lock().unwrap().get(id).cloned().ok_or_else(|| {
+            AwsError::new(AwsErrorKind::SessionNotFound)
+                .message(format!("session `{}` does not exist", id))
+        })
+    }
+
+    // 同じIDでセッションを作り直す
+    // (実行中の操作は取得済みのセッションを保持するため影響しない)
+    async fn replace(&self, id: &str, key: ProfileKey) -> Result<Arc<Session>, AwsError> {
+        let session = Arc::new(self.create(id.to_string(), key).await?);
+        self.items
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), session.clone());
+        Ok(session)
+    }
+
+    /// 開始済みのセッションの一覧
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let mut list = self
+            .items
+            .lock()
+            .unwrap()
+            .values()
+            .map(|s| s.info())
+            .collect::<Vec<SessionInfo>>();
+        list.sort_by(|a, b| a.id.cmp(&b.id));
+        list
+    }
+
+    /// セッションを終了する
+    pub fn close(&self, id: &str) {
+        self.items.lock().unwrap().remove(id);
+        super::identity::stop_watch(id);
+    }
+
+    /// キャッシュ済みのAWS Configとクライアントを破棄する
+    /// 接続設定や認証情報を変更した場合に呼び出す
+    pub fn invalidate(&self) {
+        self.profiles.lock().unwrap().clear();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // キャッシュを使用してセッションを生成する
+    async fn create(&self, id: String, key: ProfileKey) -> Result<Session, AwsError> {
+        self.check_files();
+        let generation = self.generation.load(Ordering::SeqCst);
+
+        let cached = self.profiles.lock().unwrap().get(&key).cloned();
+        let entry = match cached {
+            Some(v) => v,
+            None => {
+                let entry = ProfileEntry::load(&key).await?;
+                self.profiles
+                    .lock()
+                    .unwrap()
+                    .insert(key.clone(), entry.clone());
+                entry
+            }
+        };
+        Ok(Session::new(id, key, entry, generation))
+    }
+
+    // AWSのconfig, credentialファイルが更新されている場合はキャッシュを破棄する
+    fn check_files(&self) {
+        let current = [FileKind::Config, FileKind::Credentials]
+            .iter()
+            .map(|kind| {
+                let meta = std::fs::metadata(profile::file_path(*kind)?).ok()?;
+                Some((meta.modified().ok()?, meta.len()))
+            })
+            .collect::<Vec<Option<(SystemTime, u64)>>>();
+
+        let mut stamp = self.file_stamp.lock().unwrap();
+        if *stamp != current {
+            // 初回は比較対象がないため記録のみ
+            let changed = !stamp.is_empty();
+            *stamp = current;
+            drop(stamp);
+            if changed {
+                self.invalidate();
+            }
+        }
+    }
+}
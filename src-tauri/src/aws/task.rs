@@ -0,0 +1,47 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use once_cell::sync::Lazy;
+use tokio::task::JoinHandle;
+
+// タスクIDの採番
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// 実行中のタスク(タスクID → タスク)
+static RUNNING: Lazy<Mutex<HashMap<String, JoinHandle<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 画面から取り消しできるバックグラウンドのタスクを開始する
+/// タスクIDは`{kind}-{連番}`の形式で、タスクの生成時に引数で受け取る
+pub fn start<F, Fut>(kind: &str, f: F) -> String
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let task_id = format!("{}-{}", kind, NEXT_ID.fetch_add(1, Ordering::Relaxed));
+
+    // 登録前にタスクが終了して登録が残らないよう、ロックを取得してから開始する
+    let mut running = RUNNING.lock().unwrap();
+    let future = f(task_id.clone());
+    let id = task_id.clone();
+    let task = tokio::spawn(async move {
+        future.await;
+        RUNNING.lock().unwrap().remove(&id);
+    });
+    running.insert(task_id.clone(), task);
+    task_id
+}
+
+/// 実行中のタスクを取り消す
+/// 終了済み、または存在しない場合は何もしない
+pub fn cancel(task_id: &str) {
+    if let Some(task) = RUNNING.lock().unwrap().remove(task_id) {
+        task.abort();
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+// TLS証明書の検証はデフォルトで有効
+fn default_verify_tls() -> bool {
+    true
+}
+
+/// S3のリクエストのアドレス形式
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressingStyle {
+    // パス形式(`https://endpoint/bucket/key`)
+    Path,
+    // 仮想ホスト形式(`https://bucket.endpoint/key`)
+    VirtualHosted,
+}
+
+impl Default for AddressingStyle {
+    fn default() -> Self {
+        AddressingStyle::Path
+    }
+}
+
+// S3互換のエンドポイント(MinIO, Ceph, LocalStackなど)や
+// 認証情報を使用しない公開バケットへの接続設定
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Connection {
+    // 接続名(プロファイル名と同様に選択する)
+    pub name: String,
+
+    // エンドポイントのURL(例: http://localhost:9000)
+    // 未指定の場合はAWSのエンドポイントに接続する
+    #[serde(default)]
+    pub endpoint_url: Option<String>,
+
+    // リージョン(未指定の場合は通常のプロファイルと同様に解決する)
+    #[serde(default)]
+    pub region: Option<String>,
+
+    // 認証情報を取得するAWSプロファイル
+    // 指定がない場合はアクセスキーを使用する
+    #[serde(default)]
+    pub profile: Option<String>,
+
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+
+    #[serde(default)]
+    pub session_token: Option<String>,
+
+    // TLS証明書を検証するか否か(自己署名証明書を使用している場合は無効にする)
+    #[serde(default = "default_verify_tls")]
+    pub verify_tls: bool,
+
+    // バケットをパスとホスト名のどちらで指定するか
+    // MinIOなどはパス形式、仮想ホスト形式のみ受け付けるサービスもあるため選択できるようにする
+    #[serde(default)]
+    pub addressing_style: AddressingStyle,
+
+    // 署名なしのリクエストで公開バケットにアクセスするか否か
+    #[serde(default)]
+    pub anonymous: bool,
+
+    // バケット名を直接入力して追加したバケット
+    // 匿名アクセスではバケット一覧を取得できないためこのリストを使用する
+    #[serde(default)]
+    pub buckets: Vec<String>,
+
+    // バケットごとの階層の区切り文字(未設定の場合は`/`)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub delimiters: HashMap<String, char>,
+}
+
+impl Connection {
+    /// 画面に返却するため、シークレットアクセスキーとセッショントークンを除去する
+    pub fn redacted(mut self) -> Self {
+        self.secret_access_key = None;
+        self.session_token = None;
+        self
+    }
+
+    /// 画面から受け取った設定に、保存済みの設定から画面に返却していない値を引き継ぐ
+    /// 秘密情報は未指定かつアクセスキーが同じ場合に引き継ぎ、空文字の場合は削除する
+    /// バケットと区切り文字は個別に追加、変更するため、常に保存済みの値を引き継ぐ
+    pub fn merge_saved(mut self, saved: Option<&Connection>) -> Self {
+        if let Some(saved) = saved {
+            self.buckets = saved.buckets.clone();
+            self.delimiters = saved.delimiters.clone();
+        }
+        if let Some(saved) = saved.filter(|s| s.access_key_id == self.access_key_id) {
+            if self.secret_access_key.is_none() {
+                self.secret_access_key = saved.secret_access_key.clone();
+            }
+            if self.session_token.is_none() {
+                self.session_token = saved.session_token.clone();
+            }
+        }
+        self.secret_access_key = self.secret_access_key.filter(|v| !v.is_empty());
+        self.session_token = self.session_token.filter(|v| !v.is_empty());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(json: &str) -> Connection {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn saved() -> Connection {
+        connection(
+            r#"{
+                "name": "minio",
+                "endpoint_url": "http://localhost:9000",
+                "access_key_id": "AKID",
+                "secret_access_key": "SECRET",
+                "session_token": "TOKEN",
+                "buckets": ["public"],
+                "delimiters": {"logs": ":"}
+            }"#,
+        )
+    }
+
+    #[test]
+    fn update_keeps_buckets_and_delimiters() {
+        let edited = connection(
+            r#"{"name": "minio", "endpoint_url": "http://localhost:9001", "access_key_id": "AKID"}"#,
+        );
+        let merged = edited.merge_saved(Some(&saved()));
+        assert_eq!(
+            merged.endpoint_url.as_deref(),
+            Some("http://localhost:9001")
+        );
+        assert_eq!(merged.buckets, vec!["public".to_string()]);
+        assert_eq!(merged.delimiters.get("logs"), Some(&':'));
+    }
+
+    #[test]
+    fn update_keeps_secrets_for_the_same_access_key() {
+        let edited = connection(r#"{"name": "minio", "access_key_id": "AKID"}"#);
+        let merged = edited.merge_saved(Some(&saved()));
+        assert_eq!(merged.secret_access_key.as_deref(), Some("SECRET"));
+        assert_eq!(merged.session_token.as_deref(), Some("TOKEN"));
+    }
+
+    #[test]
+    fn update_drops_secrets_when_the_access_key_changes() {
+        let edited = connection(r#"{"name": "minio", "access_key_id": "OTHER"}"#);
+        let merged = edited.merge_saved(Some(&saved()));
+        assert_eq!(merged.secret_access_key, None);
+        assert_eq!(merged.session_token, None);
+    }
+
+    #[test]
+    fn empty_secret_removes_the_saved_value() {
+        let edited = connection(
+            r#"{"name": "minio", "access_key_id": "AKID", "secret_access_key": "NEW", "session_token": ""}"#,
+        );
+        let merged = edited.merge_saved(Some(&saved()));
+        assert_eq!(merged.secret_access_key.as_deref(), Some("NEW"));
+        assert_eq!(merged.session_token, None);
+    }
+
+    #[test]
+    fn new_connection_has_no_saved_values() {
+        let merged = connection(r#"{"name": "minio", "buckets": ["a"]}"#).merge_saved(None);
+        assert_eq!(merged.buckets, vec!["a".to_string()]);
+        assert!(merged.verify_tls);
+    }
+
+    #[test]
+    fn redacted_removes_secrets() {
+        let redacted = saved().redacted();
+        assert_eq!(redacted.access_key_id.as_deref(), Some("AKID"));
+        assert_eq!(redacted.secret_access_key, None);
+        assert_eq!(redacted.session_token, None);
+    }
+}
@@ -1,5 +1,11 @@
-use std::io::Write;
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
+pub mod connection;
 pub mod favorite;
 
 // JSONのデフォルト値
@@ -29,6 +35,15 @@ pub struct UserConfig {
     // フォルダダウンロード時にZIP圧縮するか否か
     #[serde(default = "default_dir_zip")]
     pub dir_zip: bool,
+
+    // S3互換のエンドポイントへの接続設定
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connections: Option<Vec<connection::Connection>>,
+
+    // AWSのバケットごとの階層の区切り文字(未設定の場合は`/`)
+    // 接続設定のバケットは同じ名前の別のバケットが存在するため、接続設定ごとに保持する
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub delimiters: HashMap<String, char>,
 }
 
 impl Default for UserConfig {
@@ -41,6 +56,8 @@ impl Default for UserConfig {
             favorites: None,
             download_dir: Some(dwn_path.as_os_str().to_str().unwrap().to_string()),
             dir_zip: false,
+            connections: None,
+            delimiters: HashMap::new(),
         }
     }
 }
@@ -66,6 +83,75 @@ fn __get_pathbuf() -> std::path::PathBuf {
     conf_file
 }
 
+/// アプリのデータディレクトリを取得
+pub fn data_dir() -> std::path::PathBuf {
+    // ホームディレクトリを取得
+    let opt_home = dirs::home_dir();
+
+    // データディレクトリを取得
+    let mut data_dir = opt_home.unwrap();
+    data_dir.push(".config");
+    data_dir.push("s3-explorer");
+
+    // 存在しない場合は生成
+    if !data_dir.exists() {
+        std::fs::create_dir_all(&data_dir).expect("could not create data directory");
+    }
+
+    data_dir
+}
+
+/// アプリのキャッシュディレクトリを取得
+pub fn cache_dir() -> std::path::PathBuf {
+    // キャッシュディレクトリを取得
+    let mut cache_dir = data_dir();
+    cache_dir.push("cache");
+
+    // 存在しない場合は生成
+    if !cache_dir.exists() {
+        std::fs::create_dir_all(&cache_dir).expect("could not create cache directory");
+    }
+
+    cache_dir
+}
+
+// 一時ファイル名の採番
+static TEMP_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 本人のみ読み書きできる権限でファイルに書き込む
+/// 同じディレクトリの一時ファイルに書き込んでから置き換えるため、途中で失敗しても元のファイルは壊れない
+pub fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let file_name = path.file_name().and_then(|n| n.to_str()).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid file path")
+    })?;
+    let temp = path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        file_name,
+        std::process::id(),
+        TEMP_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let result = options
+        .open(&temp)
+        .and_then(|mut f| {
+            f.write_all(contents)?;
+            f.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
 // Configファイル読み込み
 pub fn read_config() -> UserConfig {
     let conf_file = __get_pathbuf();
@@ -110,6 +196,114 @@ pub fn list_favorite() -> Vec<favorite::Favorite> {
     vec![]
 }
 
+// 接続設定の一覧を返却
+pub fn list_connections() -> Vec<connection::Connection> {
+    read_config().connections.unwrap_or_default()
+}
+
+// 画面に返却する接続設定の一覧(秘密情報を除去する)
+pub fn list_redacted_connections() -> Vec<connection::Connection> {
+    list_connections()
+        .into_iter()
+        .map(connection::Connection::redacted)
+        .collect()
+}
+
+// 名称から接続設定を取得
+pub fn find_connection(name: &str) -> Option<connection::Connection> {
+    list_connections().into_iter().find(|x| x.name == name)
+}
+
+// 接続設定を保存(同名の設定が存在する場合は上書き)
+pub fn save_connection(conn: connection::Connection) {
+    let mut conf = read_config();
+    let list = conf.connections.get_or_insert_with(Vec::new);
+    match list.iter().position(|x| x.name == conn.name) {
+        Some(p) => list[p] = conn.merge_saved(Some(&list[p])),
+        None => list.push(conn.merge_saved(None)),
+    }
+    conf.write_to_file();
+}
+
+// 接続設定にバケットを追加
+pub fn add_connection_bucket(name: &str, bucket: &str) {
+    let mut conf = read_config();
+    if let Some(conn) = conf
+        .connections
+        .as_mut()
+        .and_then(|list| list.iter_mut().find(|x| x.name == name))
+    {
+        if !conn.buckets.iter().any(|b| b == bucket) {
+            conn.buckets.push(bucket.to_string());
+        }
+    }
+    conf.write_to_file();
+}
+
+// 接続設定からバケットを削除
+pub fn remove_connection_bucket(name: &str, bucket: &str) {
+    let mut conf = read_config();
+    if let Some(conn) = conf
+        .connections
+        .as_mut()
+        .and_then(|list| list.iter_mut().find(|x| x.name == name))
+    {
+        conn.buckets.retain(|b| b != bucket);
+    }
+    conf.write_to_file();
+}
+
+// 接続設定を削除
+pub fn delete_connection(name: &str) {
+    let mut conf = read_config();
+    if let Some(list) = conf.connections.as_mut() {
+        list.retain(|x| x.name != name);
+    }
+    conf.write_to_file();
+}
+
+// バケットの区切り文字を取得(未設定の場合は`/`)
+// `connection`は接続設定のバケットの場合の接続名
+pub fn bucket_delimiter(connection: Option<&str>, bucket: &str) -> char {
+    let conf = read_config();
+    let delimiters = match connection {
+        Some(name) => conf
+            .connections
+            .iter()
+            .flatten()
+            .find(|x| x.name == name)
+            .map(|x| &x.delimiters),
+        None => Some(&conf.delimiters),
+    };
+    delimiters
+        .and_then(|d| d.get(bucket))
+        .copied()
+        .unwrap_or(crate::aws::path::DELIMITER)
+}
+
+// バケットの区切り文字を設定(未指定または`/`の場合は設定を削除)
+// `connection`は接続設定のバケットの場合の接続名
+pub fn set_bucket_delimiter(connection: Option<&str>, bucket: &str, delimiter: Option<char>) {
+    let mut conf = read_config();
+    let delimiters = match connection {
+        Some(name) => match conf
+            .connections
+            .iter_mut()
+            .flatten()
+            .find(|x| x.name == name)
+        {
+            Some(conn) => &mut conn.delimiters,
+            None => return,
+        },
+        None => &mut conf.delimiters,
+    };
+    match delimiter.filter(|d| *d != crate::aws::path::DELIMITER) {
+        Some(d) => delimiters.insert(bucket.to_string(), d),
+        None => delimiters.remove(bucket),
+    };
+    conf.write_to_file();
+}
+
 // Config情報保存
 pub fn save_config(download_dir: String, dir_zip: bool) {
     // Configファイルを読み込んで値を更新
@@ -123,24 +317,27 @@ pub fn save_config(download_dir: String, dir_zip: bool) {
 
 // UserConfigに対する処理
 impl UserConfig {
+    // 画面に返却するため、接続設定の秘密情報を除去する
+    pub fn redacted(mut self) -> Self {
+        self.connections = self.connections.map(|list| {
+            list.into_iter()
+                .map(connection::Connection::redacted)
+                .collect()
+        });
+        self
+    }
+
     // Configファイル書き込み
+    // 接続設定の認証情報を含むため、本人のみ読み書きできる権限で置き換える
     pub fn write_to_file(&self) {
         // ファイルパスを取得
         let conf_file = __get_pathbuf();
 
-        // ファイル作成
-        let f = std::fs::File::create(conf_file);
-
-        // 作成できない場合はエラー
-        if f.is_err() {
-            return;
-        }
-
         // テキストに変換
         let text = serde_json::to_string_pretty(self).unwrap();
 
-        // 書き込み
-        let _ = f.unwrap().write(text.as_bytes());
+        // 書き込み(書き込めない場合は何もしない)
+        let _ = write_private(&conf_file, text.as_bytes());
     }
 
     // お気に入り登録
@@ -1,40 +1,217 @@
-use std::error;
+use std::{any::Any, error};
+
+use aws_sdk_s3::error::HeadBucketError;
+use aws_smithy_http::result::SdkError;
+use aws_smithy_types::retry::ProvideErrorKind;
+use aws_types::credentials::CredentialsError;
+
+use crate::aws::{
+    credentials::{sso::SsoTokenExpired, vault::VaultLocked},
+    profile::ReadError,
+};
 
 pub enum AwsErrorKind {
     AccessDenied,
     ExpiredToken,
     NotFoundObject,
+    SsoTokenExpired,
+    VaultLocked,
+    // S3のエラーコード
+    NoSuchBucket,
+    NoSuchKey,
+    InvalidAccessKeyId,
+    SignatureDoesNotMatch,
+    SlowDown,
+    RequestTimeTooSkewed,
+    // 上記以外のサービスのエラー
+    Service,
+    // 認証情報を取得できない
+    Credentials,
+    // 通信エラー
+    Timeout,
+    Dns,
+    Tls,
+    Network,
+    // ローカルのファイル操作のエラー
+    Io,
+    // 画面からの指定が不正
+    InvalidArgument,
+    SessionNotFound,
+    ProfileNotFound,
+    // AWSのconfig, credentialファイルを読み込めない
+    ProfileFile,
+    Unknown,
 }
 
 enum _Error {
     Simple(AwsErrorKind),
+    Custom((AwsErrorKind, Box<dyn error::Error + Send + Sync>)),
 }
 
 pub struct AwsError {
     _error: _Error,
+    // エラーレスポンスのコードとメッセージ
+    service_code: Option<String>,
+    message: Option<String>,
+    request_id: Option<String>,
+    // 操作対象
+    bucket: Option<String>,
+    key: Option<String>,
+    retryable: bool,
 }
 
 impl AwsError {
     pub fn new(kind: AwsErrorKind) -> Self {
+        AwsError::with_error(_Error::Simple(kind))
+    }
+
+    pub fn custom<T>(kind: AwsErrorKind, error: T) -> Self
+    where
+        T: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        AwsError::with_error(_Error::Custom((kind, error.into())))
+    }
+
+    fn with_error(error: _Error) -> Self {
+        let retryable = match &error {
+            _Error::Simple(kind) => kind.retryable(),
+            _Error::Custom((kind, _)) => kind.retryable(),
+        };
         AwsError {
-            _error: _Error::Simple(kind),
+            _error: error,
+            service_code: None,
+            message: None,
+            request_id: None,
+            bucket: None,
+            key: None,
+            retryable,
         }
     }
 
-    // pub fn custom<T>(kind: AwsErrorKind, error: T) -> Self
-    // where
-    //     T: Into<Box<dyn error::Error + Send + Sync>>,
-    // {
-    //     AwsError {
-    //         _error: _Error::Custom((kind, error.into())),
-    //     }
-    // }
+    /// SDKのエラーから変換する
+    pub fn from_sdk<E>(err: SdkError<E>) -> Self
+    where
+        E: ProvideErrorKind + error::Error + Send + Sync + 'static,
+    {
+        match err {
+            SdkError::ConstructionFailure(err) => {
+                // 認証情報の取得に失敗した場合
+                let kind = crate::error::find_source::<CredentialsError>(&*err)
+                    .map(credentials_kind)
+                    .unwrap_or(AwsErrorKind::InvalidArgument);
+                AwsError::custom(kind, err)
+            }
+            SdkError::TimeoutError(err) => AwsError::custom(AwsErrorKind::Timeout, err),
+            SdkError::DispatchFailure(err) => {
+                let kind = if err.is_timeout() {
+                    AwsErrorKind::Timeout
+                } else {
+                    network_kind(&err)
+                };
+                AwsError::custom(kind, err)
+            }
+            SdkError::ResponseError { err, raw } => {
+                // レスポンスを解析できない場合
+                let status = raw.http().status();
+                let kind = if status.is_success() {
+                    AwsErrorKind::Unknown
+                } else {
+                    status_kind(status.as_u16())
+                };
+                let mut error = AwsError::custom(kind, err);
+                error.request_id = request_id(raw.http().headers());
+                error.retryable = error.retryable || status.is_server_error();
+                error
+            }
+            SdkError::ServiceError { err, raw } => {
+                let status = raw.http().status();
+                let service_code = err.code().map(|c| c.to_string());
+                let kind = match service_code.as_deref() {
+                    // HEADリクエストの404はSDKが`NotFound`とするため、操作から対象を判定する
+                    // (HeadObjectの場合はオブジェクトが存在しない)
+                    Some("NotFound") if (&err as &dyn Any).is::<HeadBucketError>() => {
+                        AwsErrorKind::NoSuchBucket
+                    }
+                    Some(code) => code_kind(code),
+                    // HEADリクエストなどボディがない場合はステータスコードから判定
+                    None => status_kind(status.as_u16()),
+                };
+                let retryable = err.retryable_error_kind().is_some() || status.is_server_error();
 
-    pub fn name(&self) -> String {
-        let kind = match &self._error {
+                let mut error = AwsError::custom(kind, err);
+                error.service_code = service_code;
+                error.request_id = request_id(raw.http().headers());
+                error.retryable = error.retryable || retryable;
+                error
+            }
+        }
+    }
+
+    /// レスポンスに含まれる個別のエラーコードから変換する
+    /// (DeleteObjectsのオブジェクトごとのエラーなど)
+    pub fn from_service_code(code: &str) -> Self {
+        let mut error = AwsError::new(code_kind(code));
+        error.service_code = Some(code.to_string());
+        error
+    }
+
+    /// 認証情報の取得エラーから変換する
+    pub fn from_credentials(err: CredentialsError) -> Self {
+        let kind = credentials_kind(&err);
+        AwsError::custom(kind, err)
+    }
+
+    /// ローカルのファイル操作のエラーから変換する
+    pub fn io<T>(err: T) -> Self
+    where
+        T: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        AwsError::custom(AwsErrorKind::Io, err)
+    }
+
+    /// 操作対象のバケットを設定する
+    pub fn bucket(mut self, bucket: impl Into<String>) -> Self {
+        self.bucket = Some(bucket.into());
+        self
+    }
+
+    /// 操作対象のキーを設定する
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// メッセージを設定する
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn kind(&self) -> &AwsErrorKind {
+        match &self._error {
             _Error::Simple(kind) => kind,
-        };
-        kind.name().to_string()
+            _Error::Custom((kind, _)) => kind,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.kind().name().to_string()
+    }
+
+    /// 再試行で成功する可能性があるか
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+
+    // 画面に表示するメッセージ
+    fn display_message(&self) -> String {
+        if let Some(m) = &self.message {
+            return m.clone();
+        }
+        match &self._error {
+            _Error::Simple(kind) => kind.description().to_string(),
+            _Error::Custom((kind, err)) => format!("{} ({})", kind.description(), err),
+        }
     }
 }
 
@@ -44,6 +221,26 @@ impl AwsErrorKind {
             AwsErrorKind::AccessDenied => "AccessDenied",
             AwsErrorKind::ExpiredToken => "ExpiredToken",
             AwsErrorKind::NotFoundObject => "NotFoundObject",
+            AwsErrorKind::SsoTokenExpired => "SsoTokenExpired",
+            AwsErrorKind::VaultLocked => "VaultLocked",
+            AwsErrorKind::NoSuchBucket => "NoSuchBucket",
+            AwsErrorKind::NoSuchKey => "NoSuchKey",
+            AwsErrorKind::InvalidAccessKeyId => "InvalidAccessKeyId",
+            AwsErrorKind::SignatureDoesNotMatch => "SignatureDoesNotMatch",
+            AwsErrorKind::SlowDown => "SlowDown",
+            AwsErrorKind::RequestTimeTooSkewed => "RequestTimeTooSkewed",
+            AwsErrorKind::Service => "Service",
+            AwsErrorKind::Credentials => "Credentials",
+            AwsErrorKind::Timeout => "Timeout",
+            AwsErrorKind::Dns => "Dns",
+            AwsErrorKind::Tls => "Tls",
+            AwsErrorKind::Network => "Network",
+            AwsErrorKind::Io => "Io",
+            AwsErrorKind::InvalidArgument => "InvalidArgument",
+            AwsErrorKind::SessionNotFound => "SessionNotFound",
+            AwsErrorKind::ProfileNotFound => "ProfileNotFound",
+            AwsErrorKind::ProfileFile => "ProfileFile",
+            AwsErrorKind::Unknown => "Unknown",
         }
     }
 
@@ -52,16 +249,124 @@ impl AwsErrorKind {
             AwsErrorKind::AccessDenied => "Access denied.",
             AwsErrorKind::ExpiredToken => "The provided token has expired.",
             AwsErrorKind::NotFoundObject => "Not found object in bucket.",
+            AwsErrorKind::SsoTokenExpired => "The SSO session has expired. Please login again.",
+            AwsErrorKind::VaultLocked => "The credential vault is locked. Please unlock it.",
+            AwsErrorKind::NoSuchBucket => "The specified bucket does not exist.",
+            AwsErrorKind::NoSuchKey => "The specified key does not exist.",
+            AwsErrorKind::InvalidAccessKeyId => "The access key ID does not exist.",
+            AwsErrorKind::SignatureDoesNotMatch => {
+                "The request signature does not match. Please check the secret access key."
+            }
+            AwsErrorKind::SlowDown => "Too many requests. Please reduce the request rate.",
+            AwsErrorKind::RequestTimeTooSkewed => {
+                "The difference between the request time and the server's time is too large."
+            }
+            AwsErrorKind::Service => "The service returned an error.",
+            AwsErrorKind::Credentials => "Could not load credentials.",
+            AwsErrorKind::Timeout => "The request timed out.",
+            AwsErrorKind::Dns => "Could not resolve the host name.",
+            AwsErrorKind::Tls => "TLS handshake failed.",
+            AwsErrorKind::Network => "Could not connect to the endpoint.",
+            AwsErrorKind::Io => "File operation failed.",
+            AwsErrorKind::InvalidArgument => "Invalid argument.",
+            AwsErrorKind::SessionNotFound => "The session does not exist.",
+            AwsErrorKind::ProfileNotFound => "The profile does not exist.",
+            AwsErrorKind::ProfileFile => "Could not read the AWS config or credentials file.",
+            AwsErrorKind::Unknown => "Unknown error.",
         }
     }
+
+    // 再試行で成功する可能性があるか
+    fn retryable(&self) -> bool {
+        matches!(
+            self,
+            AwsErrorKind::SlowDown
+                | AwsErrorKind::Timeout
+                | AwsErrorKind::Dns
+                | AwsErrorKind::Network
+        )
+    }
+}
+
+// サービスのエラーコードから種類を判定
+fn code_kind(code: &str) -> AwsErrorKind {
+    match code {
+        "AccessDenied" | "AccessDeniedException" | "AllAccessDisabled" => {
+            AwsErrorKind::AccessDenied
+        }
+        "ExpiredToken" | "ExpiredTokenException" | "TokenRefreshRequired" | "RequestExpired" => {
+            AwsErrorKind::ExpiredToken
+        }
+        "NoSuchBucket" => AwsErrorKind::NoSuchBucket,
+        "NoSuchKey" | "NotFound" => AwsErrorKind::NoSuchKey,
+        "InvalidAccessKeyId" | "InvalidClientTokenId" | "UnrecognizedClientException" => {
+            AwsErrorKind::InvalidAccessKeyId
+        }
+        "SignatureDoesNotMatch" => AwsErrorKind::SignatureDoesNotMatch,
+        "SlowDown" | "Throttling" | "ThrottlingException" | "RequestLimitExceeded" => {
+            AwsErrorKind::SlowDown
+        }
+        "RequestTimeTooSkewed" => AwsErrorKind::RequestTimeTooSkewed,
+        _ => AwsErrorKind::Service,
+    }
+}
+
+// HTTPステータスコードから種類を判定
+fn status_kind(status: u16) -> AwsErrorKind {
+    match status {
+        403 => AwsErrorKind::AccessDenied,
+        404 => AwsErrorKind::NotFoundObject,
+        503 => AwsErrorKind::SlowDown,
+        _ => AwsErrorKind::Service,
+    }
+}
+
+// 認証情報の取得エラーの種類を判定
+fn credentials_kind(err: &CredentialsError) -> AwsErrorKind {
+    if crate::error::find_source::<SsoTokenExpired>(err).is_some() {
+        AwsErrorKind::SsoTokenExpired
+    } else if crate::error::find_source::<VaultLocked>(err).is_some() {
+        AwsErrorKind::VaultLocked
+    } else if let CredentialsError::ProviderTimedOut(_) = err {
+        AwsErrorKind::Timeout
+    } else {
+        AwsErrorKind::Credentials
+    }
+}
+
+// 通信エラーの種類を判定
+// 発生元のエラーの型が公開されていないためメッセージから判定する
+fn network_kind(err: &(dyn error::Error + 'static)) -> AwsErrorKind {
+    if crate::error::find_source::<rustls::TLSError>(err).is_some() {
+        return AwsErrorKind::Tls;
+    }
+
+    let mut current = Some(err);
+    while let Some(e) = current {
+        let message = e.to_string().to_lowercase();
+        if message.contains("dns error") || message.contains("failed to lookup address") {
+            return AwsErrorKind::Dns;
+        }
+        if message.contains("certificate") || message.contains("tls") {
+            return AwsErrorKind::Tls;
+        }
+        current = e.source();
+    }
+    AwsErrorKind::Network
+}
+
+// レスポンスヘッダからリクエストIDを取得
+fn request_id(headers: &http::HeaderMap) -> Option<String> {
+    ["x-amz-request-id", "x-amzn-requestid"]
+        .iter()
+        .filter_map(|k| headers.get(*k))
+        .find_map(|v| v.to_str().ok())
+        .map(|v| v.to_string())
 }
 
 impl std::fmt::Display for AwsError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self._error {
-            _Error::Simple(s) => f.write_str(s.description()),
-            // _Error::Custom(c) => f.write_str(c.0.description()),
-        }
+        f.write_str(&self.display_message())
     }
 }
 
@@ -75,7 +380,40 @@ impl std::error::Error for AwsError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match &self._error {
             _Error::Simple(_) => None,
-            // _Error::Custom(c) => c.1.source(),
+            _Error::Custom(c) => Some(c.1.as_ref()),
         }
     }
 }
+
+/// 画面に返却する形式
+/// `{ code, message, service_code, request_id, bucket, key, retryable }`
+impl serde::Serialize for AwsError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        let mut s = serializer.serialize_struct("AwsError", 7)?;
+        s.serialize_field("code", self.kind().name())?;
+        s.serialize_field("message", &self.display_message())?;
+        s.serialize_field("service_code", &self.service_code)?;
+        s.serialize_field("request_id", &self.request_id)?;
+        s.serialize_field("bucket", &self.bucket)?;
+        s.serialize_field("key", &self.key)?;
+        s.serialize_field("retryable", &self.retryable)?;
+        s.end()
+    }
+}
+
+impl From<std::io::Error> for AwsError {
+    fn from(err: std::io::Error) -> Self {
+        AwsError::io(err)
+    }
+}
+
+impl From<ReadError> for AwsError {
+    fn from(err: ReadError) -> Self {
+        AwsError::custom(AwsErrorKind::ProfileFile, err)
+    }
+}
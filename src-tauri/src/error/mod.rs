@@ -1 +1,16 @@
 pub mod aws_error;
+
+/// エラーの発生元をたどって指定の型のエラーを探す
+pub fn find_source<'a, T>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a T>
+where
+    T: std::error::Error + 'static,
+{
+    let mut current = Some(err);
+    while let Some(e) = current {
+        if let Some(t) = e.downcast_ref::<T>() {
+            return Some(t);
+        }
+        current = e.source();
+    }
+    None
+}
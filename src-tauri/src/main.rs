@@ -14,7 +14,11 @@ mod files;
 #[tokio::main]
 async fn main() {
     tauri::Builder::default()
+        .manage(aws::session::Sessions::default())
         .setup(|app| {
+            // MFAコード入力要求のイベント通知に使用する
+            aws::credentials::mfa::init(app.handle());
+
             #[cfg(debug_assertions)] // only include this code on debug builds
             {
                 let window = app.get_window("main").unwrap();
@@ -31,8 +35,44 @@ async fn main() {
             list_profiles,
             set_profile,
             get_selected_profile,
+            get_profile,
+            create_profile,
+            update_profile,
+            rename_profile,
+            delete_profile,
+            list_connections,
+            save_connection,
+            delete_connection,
+            get_region,
+            set_region,
+            submit_mfa_code,
+            list_credential_sources,
+            get_vault_status,
+            create_vault,
+            unlock_vault,
+            lock_vault,
+            change_vault_password,
+            set_vault_credentials,
+            delete_vault_credentials,
+            cancel_mfa_code,
+            open_session,
+            list_sessions,
+            close_session,
+            get_caller_identity,
+            check_profiles,
+            export_credentials,
             list_buckets,
+            add_bucket,
+            remove_bucket,
+            get_bucket_delimiter,
+            set_bucket_delimiter,
             list_objects,
+            start_list_objects,
+            next_list_objects,
+            cancel_list_objects,
+            start_search,
+            cancel_search,
+            preview_object,
             get_objects,
             delete_objects,
             create_folder,
@@ -46,7 +86,7 @@ async fn main() {
 
 #[tauri::command]
 fn get_config() -> UserConfig {
-    config::read_config()
+    config::read_config().redacted()
 }
 
 #[tauri::command]
@@ -94,7 +134,7 @@ fn get_home_dir() -> Option<String> {
 }
 
 #[tauri::command]
-fn list_profiles() -> Vec<String> {
+fn list_profiles() -> Result<Vec<String>, error::aws_error::AwsError> {
     aws::list_profile_name()
 }
 
@@ -110,35 +150,334 @@ fn get_selected_profile() -> Option<String> {
 }
 
 #[tauri::command]
-async fn list_buckets() -> Result<Vec<aws::s3::S3Bucket>, String> {
-    match aws::s3::list_buckets().await {
-        Ok(list) => Ok(list),
-        Err(err) => Err(err.name()),
-    }
+fn get_profile(
+    name: String,
+) -> Result<Option<aws::profile::AWSProfile>, error::aws_error::AwsError> {
+    aws::get_profile(&name)
 }
 
 #[tauri::command]
-async fn list_objects(bucket_name: String, prefix: Option<String>) -> Vec<aws::s3::S3Object> {
-    match aws::s3::list_objects(bucket_name.to_string(), prefix).await {
-        Ok(list) => list,
-        Err(_) => Vec::new(),
-    }
+fn create_profile(name: String, profile: aws::editor::ProfileInput) -> Result<(), String> {
+    aws::editor::create_profile(&name, &profile).map_err(|err| format!("{}", err))
+}
+
+#[tauri::command]
+fn update_profile(name: String, profile: aws::editor::ProfileInput) -> Result<(), String> {
+    aws::editor::update_profile(&name, &profile).map_err(|err| format!("{}", err))
+}
+
+#[tauri::command]
+fn rename_profile(old_name: String, new_name: String) -> Result<(), String> {
+    aws::editor::rename_profile(&old_name, &new_name).map_err(|err| format!("{}", err))
+}
+
+#[tauri::command]
+fn delete_profile(name: String) -> Result<(), String> {
+    aws::editor::delete_profile(&name).map_err(|err| format!("{}", err))
+}
+
+#[tauri::command]
+fn list_connections() -> Vec<config::connection::Connection> {
+    config::list_redacted_connections()
+}
+
+#[tauri::command]
+fn save_connection(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    connection: config::connection::Connection,
+) -> Result<(), String> {
+    aws::save_connection(connection)?;
+    sessions.invalidate();
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_connection(sessions: tauri::State<'_, aws::session::Sessions>, name: String) {
+    config::delete_connection(&name);
+    sessions.invalidate();
+}
+
+#[tauri::command]
+async fn get_region(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+) -> Result<aws::region::ResolvedRegion, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    Ok(session.resolved_region().clone())
+}
+
+// 上書きしたリージョンは指定のセッションのみで有効
+#[tauri::command]
+async fn set_region(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    region: Option<String>,
+) -> Result<aws::region::ResolvedRegion, error::aws_error::AwsError> {
+    let session = sessions.set_region(&session_id, region).await?;
+    Ok(session.resolved_region().clone())
+}
+
+#[tauri::command]
+fn submit_mfa_code(profile: String, code: String) -> Result<(), String> {
+    aws::credentials::mfa::submit_code(&profile, code)
+}
+
+#[tauri::command]
+fn cancel_mfa_code(profile: String) {
+    aws::credentials::mfa::cancel(&profile)
+}
+
+#[tauri::command]
+fn list_credential_sources() -> Vec<aws::credentials::sources::SourceInfo> {
+    aws::credentials::sources::list()
+}
+
+#[tauri::command]
+fn get_vault_status() -> aws::credentials::vault::VaultStatus {
+    aws::credentials::vault::status()
+}
+
+#[tauri::command]
+async fn create_vault(password: String) -> Result<(), String> {
+    aws::credentials::vault::create(&password).await
+}
+
+#[tauri::command]
+async fn unlock_vault(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    password: String,
+) -> Result<(), String> {
+    aws::credentials::vault::unlock(&password).await?;
+    sessions.invalidate();
+    Ok(())
+}
+
+// 保管庫の内容が変わった場合は、キャッシュ済みの認証情報を使用しないようセッションを作り直す
+#[tauri::command]
+fn lock_vault(sessions: tauri::State<'_, aws::session::Sessions>) {
+    aws::credentials::vault::lock();
+    sessions.invalidate();
+}
+
+#[tauri::command]
+async fn change_vault_password(current: String, new_password: String) -> Result<(), String> {
+    aws::credentials::vault::change_password(&current, &new_password).await
+}
+
+#[tauri::command]
+fn set_vault_credentials(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    profile: String,
+    credentials: aws::credentials::vault::VaultCredentials,
+) -> Result<(), String> {
+    aws::credentials::vault::set_credentials(&profile, credentials)?;
+    sessions.invalidate();
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_vault_credentials(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    profile: String,
+) -> Result<(), String> {
+    aws::credentials::vault::delete_credentials(&profile)?;
+    sessions.invalidate();
+    Ok(())
+}
+
+#[tauri::command]
+async fn open_session(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    profile: Option<String>,
+) -> Result<aws::session::SessionInfo, error::aws_error::AwsError> {
+    // 指定がない場合は選択中のプロファイルを使用
+    let profile = profile.or_else(aws::get_selected_profile);
+    sessions.open(profile).await
+}
+
+#[tauri::command]
+fn list_sessions(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+) -> Vec<aws::session::SessionInfo> {
+    sessions.list()
+}
+
+#[tauri::command]
+fn close_session(sessions: tauri::State<'_, aws::session::Sessions>, session_id: String) {
+    sessions.close(&session_id)
+}
+
+#[tauri::command]
+async fn get_caller_identity(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+) -> Result<aws::identity::CallerIdentity, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    aws::identity::caller_identity(&session).await
+}
+
+#[tauri::command]
+async fn export_credentials(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    format: aws::export::ExportFormat,
+) -> Result<String, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    aws::export::export_credentials(&session, format).await
+}
+
+#[tauri::command]
+async fn check_profiles() -> Result<Vec<aws::health::ProfileHealth>, error::aws_error::AwsError> {
+    aws::health::check_all().await
+}
+
+#[tauri::command]
+async fn list_buckets(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+) -> Result<Vec<aws::s3::S3Bucket>, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    aws::s3::list_buckets(&session).await
+}
+
+#[tauri::command]
+async fn add_bucket(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    bucket_name: String,
+) -> Result<aws::s3::S3Bucket, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    aws::s3::add_bucket(&session, bucket_name).await
+}
+
+#[tauri::command]
+async fn remove_bucket(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    bucket_name: String,
+) -> Result<(), error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    aws::s3::remove_bucket(&session, &bucket_name);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_bucket_delimiter(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    bucket_name: String,
+) -> Result<String, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    Ok(session.delimiter(&bucket_name).to_string())
+}
+
+#[tauri::command]
+async fn set_bucket_delimiter(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    bucket_name: String,
+    delimiter: Option<String>,
+) -> Result<(), error::aws_error::AwsError> {
+    // 区切り文字は1文字のみ指定できる
+    let delimiter = match delimiter.as_deref().filter(|d| !d.is_empty()) {
+        Some(d) => {
+            let mut chars = d.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if !c.is_control() && !c.is_whitespace() => Some(c),
+                _ => {
+                    return Err(error::aws_error::AwsError::new(
+                        error::aws_error::AwsErrorKind::InvalidArgument,
+                    )
+                    .message(format!("invalid delimiter `{}`", d)))
+                }
+            }
+        }
+        None => None,
+    };
+    let session = sessions.get(&session_id).await?;
+    session.set_delimiter(&bucket_name, delimiter);
+    Ok(())
+}
+
+#[tauri::command]
+async fn list_objects(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    bucket_name: String,
+    prefix: Option<String>,
+    recursive: Option<bool>,
+) -> Result<aws::s3::S3ObjectList, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    let recursive = recursive.unwrap_or(false);
+    aws::s3::list_objects(&session, bucket_name, prefix, recursive).await
+}
+
+#[tauri::command]
+async fn start_list_objects(
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    bucket_name: String,
+    prefix: Option<String>,
+    recursive: Option<bool>,
+) -> Result<String, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    let recursive = recursive.unwrap_or(false);
+    Ok(aws::listing::start(
+        app,
+        session,
+        bucket_name,
+        prefix,
+        recursive,
+    ))
+}
+
+#[tauri::command]
+fn next_list_objects(listing_id: String) -> Result<(), error::aws_error::AwsError> {
+    aws::listing::next_page(&listing_id)
+}
+
+#[tauri::command]
+fn cancel_list_objects(listing_id: String) {
+    aws::listing::cancel(&listing_id)
+}
+
+#[tauri::command]
+async fn start_search(
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    bucket_name: String,
+    query: aws::search::SearchQuery,
+) -> Result<String, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    aws::search::start(app, session, bucket_name, query)
+}
+
+#[tauri::command]
+fn cancel_search(search_id: String) {
+    aws::search::cancel(&search_id)
 }
 
 #[tauri::command]
 async fn get_objects(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
     bucket_name: String,
     objects: Vec<aws::s3::S3OperationObject>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+
     // 返却用のリスト
     let mut result = Vec::<String>::new();
+    let mut error = None;
 
     for obj in objects {
         // ダウンロード対象がフォルダかどうかで処理を変更する
         let res = if obj.is_folder {
-            aws::s3::get_folder_object(bucket_name.clone(), obj).await
+            aws::s3::get_folder_object(&session, bucket_name.clone(), obj).await
         } else {
-            aws::s3::get_object(bucket_name.clone(), obj).await
+            aws::s3::get_object(&session, bucket_name.clone(), obj).await
         };
 
         match res {
@@ -146,44 +485,62 @@ async fn get_objects(
                 result.push(p);
             }
             Err(err) => {
-                // エラーがあった場合はメッセージとして返却するが、
-                // 他のダウンロードは継続する
-                println!("{}", err);
+                // エラーがあった場合も他のダウンロードは継続し、最初のエラーを返却する
+                if error.is_none() {
+                    error = Some(err);
+                }
             }
         }
     }
-    Ok(result)
+    match error {
+        Some(err) => Err(err),
+        None => Ok(result),
+    }
+}
+
+#[tauri::command]
+async fn preview_object(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    bucket_name: String,
+    key: String,
+) -> Result<aws::s3::S3ObjectPreview, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    aws::s3::preview_object(&session, bucket_name, key).await
 }
 
 #[tauri::command]
 async fn delete_objects(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
     bucket_name: String,
     objects: Vec<aws::s3::S3OperationObject>,
-) -> Result<(), String> {
-    match aws::s3::delete_objects(bucket_name, objects).await {
-        Ok(_) => Ok(()),
-        Err(err) => Err(format!("{}", err)),
-    }
+) -> Result<(), error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    aws::s3::delete_objects(&session, bucket_name, objects).await
 }
 
 #[tauri::command]
-async fn create_folder(bucket_name: String, prefix: String) -> Result<bool, String> {
-    match aws::s3::create_folder(bucket_name, prefix).await {
-        Ok(result) => Ok(result),
-        Err(err) => Err(format!("{}", err)),
-    }
+async fn create_folder(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
+    bucket_name: String,
+    prefix: String,
+) -> Result<bool, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    aws::s3::create_folder(&session, bucket_name, prefix).await
 }
 
 #[tauri::command]
 async fn put_object(
+    sessions: tauri::State<'_, aws::session::Sessions>,
+    session_id: String,
     bucket_name: String,
     prefix: Option<String>,
     file_path: String,
-) -> Result<bool, String> {
-    match aws::s3::put_object(bucket_name, prefix, file_path).await {
-        Ok(result) => Ok(result),
-        Err(err) => Err(format!("{}", err)),
-    }
+) -> Result<bool, error::aws_error::AwsError> {
+    let session = sessions.get(&session_id).await?;
+    aws::s3::put_object(&session, bucket_name, prefix, file_path).await
 }
 
 #[tauri::command]
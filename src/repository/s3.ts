@@ -1,3 +1,4 @@
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
 import { invoke } from '@tauri-apps/api/tauri';
 
 // S3Bucket
@@ -7,6 +8,39 @@ export interface S3BucketInterface {
   location: string;
 }
 
+// Rust側のエラー
+export interface AwsError {
+  // エラーの種類(`AccessDenied`, `NoSuchBucket`など)
+  code: string;
+  message: string;
+  // S3から返却されたエラーコード、リクエストID
+  service_code: string | null;
+  request_id: string | null;
+  // 操作対象のバケット、キー
+  bucket: string | null;
+  key: string | null;
+  // 再試行で成功する可能性があるか
+  retryable: boolean;
+}
+
+// リージョンの取得元
+// 上書き設定 → プロファイル → 環境変数 → デフォルトの順で解決される
+export type RegionSource = 'override' | 'profile' | 'environment' | 'default';
+
+// セッションで使用するリージョン
+export interface ResolvedRegion {
+  name: string;
+  source: RegionSource;
+}
+
+// プロファイルごとのセッション情報
+export interface SessionInfo {
+  id: string;
+  profile: string | null;
+  region: string | null;
+  region_source: RegionSource;
+}
+
 // S3 favorite
 export interface S3Favorite {
   bucket: string;
@@ -14,21 +48,72 @@ export interface S3Favorite {
 }
 
 export class S3ProileInfo {
+  // 表示中のプロファイルのセッション
+  session: SessionInfo | null = null;
   buckets: Array<S3BucketInterface> = [];
   favorites: Array<S3Favorite> = [];
-  errored: boolean = false;
+  // バケット一覧の取得に失敗した場合のエラー
+  error: AwsError | null = null;
+
+  get errored(): boolean {
+    return this.error != null;
+  }
+
+  // セッションIDを取得
+  get sessionId(): string | undefined {
+    return this.session?.id;
+  }
+
+  // セッションのリージョンを上書きする
+  // 空の場合は上書きを解除してプロファイルのリージョンに戻す
+  // 上書きは表示中のセッションのみで有効
+  async setRegion(region: string | null): Promise<ResolvedRegion | null> {
+    if (!this.session) {
+      return null;
+    }
+    const resolved = await invoke<ResolvedRegion>('set_region', {
+      sessionId: this.session.id,
+      region: region
+    });
+    this.session.region = resolved.name;
+    this.session.region_source = resolved.source;
+    return resolved;
+  }
+
+  // 指定のプロファイルのセッションを開く
+  // 同じプロファイルのセッションが開いている場合はそのまま使用する
+  async openSession(profile: string | null): Promise<SessionInfo> {
+    if (this.session && this.session.profile == profile) {
+      return this.session;
+    }
+
+    // 前のプロファイルのセッションは閉じる
+    if (this.session) {
+      await invoke('close_session', { sessionId: this.session.id });
+      this.session = null;
+    }
+    this.session = await invoke<SessionInfo>('open_session', {
+      profile: profile
+    });
+    return this.session;
+  }
 
-  listBuckets() {
-    invoke<Array<S3BucketInterface>>('list_buckets')
+  listBuckets(profile: string | null) {
+    this.openSession(profile)
+      .then((session) =>
+        invoke<Array<S3BucketInterface>>('list_buckets', {
+          sessionId: session.id
+        })
+      )
       .then((res) => {
         this.buckets = res;
-        this.errored = false;
+        this.error = null;
 
         this.listFavorite();
       })
-      .catch((e) => {
+      .catch((e: AwsError) => {
         this.buckets = [];
-        this.errored = true;
+        this.error = e;
       });
   }
 
@@ -90,6 +175,27 @@ export interface S3ObjectInterface {
   is_folder: boolean;
 }
 
+// 一覧に含まれるオブジェクトの件数、合計サイズ(フォルダは含まない)
+export interface S3ObjectTotals {
+  count: number;
+  size: number;
+}
+
+// `objects-page`イベントで通知される1ページ分の一覧
+interface ObjectsPage {
+  listing_id: string;
+  bucket: string;
+  prefix: string | null;
+  // 1始まりのページ番号
+  page: number;
+  objects: Array<S3ObjectInterface>;
+  // 1ページ目からの累計
+  totals: S3ObjectTotals;
+  // 最後のページの場合はtrue(失敗した場合を含む)
+  done: boolean;
+  error: AwsError | null;
+}
+
 // S3オブジェクトダウンロード,削除時のRustとのインタフェース
 interface S3OperationObject {
   prefix: string;
@@ -103,15 +209,156 @@ interface S3ObjectsInterface {
   list: Array<S3ObjectInterface>;
 }
 
+// 検索条件に一致したオブジェクト
+export interface SearchMatch {
+  // バケット内のキー(プレフィックスを含む)
+  key: string;
+  size: number;
+  last_modified: string | null;
+  storage_class: string;
+}
+
+// `search-progress`イベントで通知される検索の途中経過
+interface SearchProgress {
+  search_id: string;
+  bucket: string;
+  // 前回の通知以降に一致したオブジェクト
+  matches: Array<SearchMatch>;
+  // 確認済みのオブジェクト数、一致したオブジェクト数(累計)
+  scanned: number;
+  matched: number;
+  // 検索が終了した場合はtrue(失敗した場合を含む)
+  done: boolean;
+  error: AwsError | null;
+}
+
+// バケット内のキーの検索
+export class S3Search {
+  // 検索結果を表示中
+  active: boolean = false;
+  // 実行中の検索ID(終了した場合はnull)
+  searchId: string | null = null;
+  running: boolean = false;
+
+  pattern: string = '';
+  matches: Array<SearchMatch> = [];
+  scanned: number = 0;
+  matched: number = 0;
+  error: AwsError | null = null;
+
+  // 検索IDが返却される前に届いた途中経過
+  earlyProgress: Array<SearchProgress> = [];
+  unlisten: Promise<UnlistenFn> | null = null;
+
+  // 指定のプレフィックス配下をglobのパターンで検索する
+  async start(
+    sessionId: string | undefined,
+    bucket: string,
+    prefix: string | null,
+    pattern: string
+  ) {
+    this.cancel();
+    this.active = true;
+    this.running = true;
+    this.pattern = pattern;
+    this.matches = [];
+    this.scanned = 0;
+    this.matched = 0;
+    this.error = null;
+
+    await this.listenProgress();
+    try {
+      const searchId = await invoke<string>('start_search', {
+        sessionId: sessionId,
+        bucketName: bucket,
+        query: {
+          prefix: prefix,
+          pattern: pattern
+        }
+      });
+      this.searchId = searchId;
+
+      const list = this.earlyProgress.filter((p) => p.search_id == searchId);
+      this.earlyProgress = [];
+      list.forEach((p) => this.onProgress(p));
+    } catch (e) {
+      this.running = false;
+      this.error = e as AwsError;
+    }
+  }
+
+  // 実行中の検索を取り消す
+  cancel() {
+    if (this.searchId) {
+      invoke('cancel_search', { searchId: this.searchId });
+      this.searchId = null;
+    }
+    this.running = false;
+  }
+
+  // 検索を取り消して結果を破棄する
+  clear() {
+    this.cancel();
+    this.active = false;
+    this.matches = [];
+    this.error = null;
+  }
+
+  // 途中経過の通知を受け取る
+  listenProgress(): Promise<UnlistenFn> {
+    if (!this.unlisten) {
+      this.unlisten = listen<SearchProgress>('search-progress', (event) => {
+        // 検索IDの返却前に届いた場合は返却されるまで保持する
+        if (this.running && !this.searchId) {
+          this.earlyProgress.push(event.payload);
+          return;
+        }
+        this.onProgress(event.payload);
+      });
+    }
+    return this.unlisten;
+  }
+
+  // 一致したオブジェクトを結果に追加
+  onProgress(progress: SearchProgress) {
+    // 取り消した検索の通知は無視する
+    if (progress.search_id != this.searchId) {
+      return;
+    }
+    this.matches = this.matches.concat(progress.matches);
+    this.scanned = progress.scanned;
+    this.matched = progress.matched;
+    if (progress.done) {
+      this.error = progress.error;
+      this.searchId = null;
+      this.running = false;
+    }
+  }
+}
+
 export class S3Objects implements S3ObjectsInterface {
   s3Info: S3ProileInfo;
   bucket: string | null = null;
   prefix: string | null = null;
 
   list: S3ObjectInterface[] = [];
+  totals: S3ObjectTotals = { count: 0, size: 0 };
+  // 直前の操作に失敗した場合のエラー
+  error: AwsError | null = null;
 
   refetch: boolean = true;
 
+  // 取得中の一覧取得ID(最後のページを受け取った場合はnull)
+  listingId: string | null = null;
+  // 次のページを取得中
+  loading: boolean = false;
+  // 一覧取得IDが返却される前に届いたページ
+  earlyPages: Array<ObjectsPage> = [];
+  unlisten: Promise<UnlistenFn> | null = null;
+
+  // 選択中のバケットの検索
+  search: S3Search = new S3Search();
+
   // コンストラクタ
   constructor(s3Info: S3ProileInfo) {
     this.s3Info = s3Info;
@@ -127,12 +374,14 @@ export class S3Objects implements S3ObjectsInterface {
     this.bucket = b;
     this.prefix = null;
     this.refetch = true;
+    this.search.clear();
   }
 
   // プレフィックス(フォルダ)を更新
   updatePrefix(p: string | null) {
     this.prefix = p;
     this.refetch = true;
+    this.search.clear();
   }
 
   // バケット名とプレフィックス(フォルダ)を更新
@@ -149,6 +398,7 @@ export class S3Objects implements S3ObjectsInterface {
       this.prefix = p;
     }
     this.refetch = true;
+    this.search.clear();
   }
 
   // 強制アップデート
@@ -171,57 +421,167 @@ export class S3Objects implements S3ObjectsInterface {
     // ファイルタイプの場合は選択状態にする
   }
 
+  // 表示中のフォルダ配下を検索
+  searchObjects(pattern: string) {
+    if (!this.bucket) {
+      return;
+    }
+    if (pattern.length == 0) {
+      this.search.clear();
+      return;
+    }
+    this.search.start(this.s3Info.sessionId, this.bucket, this.prefix, pattern);
+  }
+
+  // 続きのページが存在するか
+  get hasMore(): boolean {
+    return this.listingId != null;
+  }
+
   // バケット情報を取得
-  listObjects(callback: Function) {
+  // 1ページ目のみ取得し、続きは`loadMore`で取得する
+  async listObjects(callback: Function) {
     if (!this.bucket || !this.refetch) {
       return;
     }
-    invoke<Array<S3ObjectInterface>>('list_objects', {
-      bucketName: this.bucket,
-      prefix: this.prefix
-    }).then((res) => {
-      this.refetch = false;
-      this.list = res;
-      callback(res);
-    });
+    this.refetch = false;
+
+    // 取得中の一覧は取り消す
+    this.cancelListing();
+    this.list = [];
+    this.totals = { count: 0, size: 0 };
+    this.error = null;
+    this.loading = true;
+    callback();
+
+    await this.listenPages();
+    try {
+      const listingId = await invoke<string>('start_list_objects', {
+        sessionId: this.s3Info.sessionId,
+        bucketName: this.bucket,
+        prefix: this.prefix
+      });
+      this.listingId = listingId;
+
+      const pages = this.earlyPages.filter((p) => p.listing_id == listingId);
+      this.earlyPages = [];
+      pages.forEach((p) => this.onPage(p));
+    } catch (e) {
+      this.loading = false;
+      this.error = e as AwsError;
+    }
+  }
+
+  // 次のページを取得
+  loadMore() {
+    if (!this.listingId || this.loading) {
+      return;
+    }
+    this.loading = true;
+    invoke('next_list_objects', { listingId: this.listingId }).catch(
+      (e: AwsError) => {
+        this.loading = false;
+        this.listingId = null;
+        this.error = e;
+      }
+    );
+  }
+
+  // 取得中の一覧を取り消す
+  cancelListing() {
+    if (this.listingId) {
+      invoke('cancel_list_objects', { listingId: this.listingId });
+      this.listingId = null;
+    }
+    this.loading = false;
+  }
+
+  // ページの通知を受け取る
+  listenPages(): Promise<UnlistenFn> {
+    if (!this.unlisten) {
+      this.unlisten = listen<ObjectsPage>('objects-page', (event) => {
+        // 一覧取得IDの返却前に届いた場合は返却されるまで保持する
+        if (this.loading && !this.listingId) {
+          this.earlyPages.push(event.payload);
+          return;
+        }
+        this.onPage(event.payload);
+      });
+    }
+    return this.unlisten;
+  }
+
+  // 取得したページを一覧に追加
+  onPage(page: ObjectsPage) {
+    // 取り消した一覧のページは無視する
+    if (page.listing_id != this.listingId) {
+      return;
+    }
+    this.list = this.list.concat(page.objects);
+    this.totals = page.totals;
+    this.error = page.error;
+    this.loading = false;
+    if (page.done) {
+      this.listingId = null;
+    }
   }
 
   // オブジェクトをダウンロード
   getObjects(objects: S3OperationObject[], callback: Function) {
     // ダウンロード
     invoke<Array<S3ObjectInterface>>('get_objects', {
+      sessionId: this.s3Info.sessionId,
       bucketName: this.bucket,
       objects: objects
-    }).then((_) => {
-      callback();
-    });
+    })
+      .then((_) => {
+        this.error = null;
+        callback();
+      })
+      .catch((e: AwsError) => {
+        this.error = e;
+        callback();
+      });
   }
 
   // オブジェクトアップロード
   putObject(filePath: string) {
     invoke<boolean>('put_object', {
+      sessionId: this.s3Info.sessionId,
       bucketName: this.bucket!,
       prefix: this.prefix,
       filePath: filePath
-    }).then((result) => {
-      // 正常に登録が完了した場合は再取得
-      if (result) {
-        this.forceUpdate();
-      }
-    });
+    })
+      .then((result) => {
+        // 正常に登録が完了した場合は再取得
+        if (result) {
+          this.forceUpdate();
+        }
+      })
+      .catch((e: AwsError) => {
+        this.error = e;
+      });
   }
 
   // オブジェクトを削除
   removeObjects(objects: S3OperationObject[], callback: Function) {
     // 削除リクエスト
     invoke<Array<S3ObjectInterface>>('delete_objects', {
+      sessionId: this.s3Info.sessionId,
       bucketName: this.bucket,
       objects: objects
-    }).then((res) => {
-      callback();
-      // 再取得を行うためにフラグを立てる
-      this.refetch = true;
-    });
+    })
+      .then((res) => {
+        callback();
+        // 再取得を行うためにフラグを立てる
+        this.refetch = true;
+      })
+      .catch((e: AwsError) => {
+        this.error = e;
+        callback();
+        // 一部のみ削除された可能性があるため再取得する
+        this.refetch = true;
+      });
   }
 
   // フォルダ作成
@@ -236,15 +596,21 @@ export class S3Objects implements S3ObjectsInterface {
     }
     // 作成リクエスト
     invoke<boolean>('create_folder', {
+      sessionId: this.s3Info.sessionId,
       bucketName: this.bucket,
       prefix: key
-    }).then((result) => {
-      // 正常に登録が完了した場合は再取得
-      if (result) {
-        this.forceUpdate();
-      }
-      callback();
-    });
+    })
+      .then((result) => {
+        // 正常に登録が完了した場合は再取得
+        if (result) {
+          this.forceUpdate();
+        }
+        callback();
+      })
+      .catch((e: AwsError) => {
+        this.error = e;
+        callback();
+      });
   }
 }
 